@@ -1,37 +1,471 @@
 #[macro_use]
 extern crate serde;
-use candid::{Decode, Encode};
-use ic_cdk::api::time;
+mod migration;
+mod validation;
+
+use candid::{Decode, Encode, Principal};
+#[cfg(not(test))]
+use ic_cdk::{
+    api::{is_controller, time},
+    caller,
+};
+#[cfg(test)]
+use tests::{caller, is_controller, time};
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
-use ic_stable_structures::{BoundedStorable, Cell, DefaultMemoryImpl, StableBTreeMap, Storable};
-use std::{borrow::Cow, cell::RefCell};
+use ic_stable_structures::{BoundedStorable, Cell, DefaultMemoryImpl, Log, StableBTreeMap, Storable};
+use migration::Versioned;
+use std::{borrow::Cow, cell::RefCell, collections::BTreeMap, marker::PhantomData, ops::Bound, thread::LocalKey};
+use validation::{Validator, MAX_GRADE_LEVEL, MAX_LONG_TEXT_LENGTH, MAX_NAME_LENGTH, MAX_TEXT_LENGTH};
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 type IdCell = Cell<u64, Memory>;
+// Maps (owner_id, child_id) pairs, for one-to-many lookups kept outside the bounded records.
+type IdIndex<K> = StableBTreeMap<(K, u64), (), Memory>;
+// Maps (normalized key, id) pairs, for lookups of records by email or name.
+type StringIndex<K> = StableBTreeMap<(IndexKey, K), (), Memory>;
+// Maps IDs to the archived records of one entity, kept apart so that normal lookups skip them.
+type Archive<K, T> = StableBTreeMap<K, Archived<T>, Memory>;
+
+/// Represents the errors returned by the canister endpoints.
+#[derive(candid::CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+enum Error {
+    NotFound { entity: String, id: u64 },
+    InvalidInput { field: String, reason: String },
+    Unauthorized { reason: String },
+    Conflict { entity: String, reason: String },
+    CapacityExceeded { entity: String, id: u64, capacity: u32 },
+    EmailInUse { email: String, entity: String, id: u64 },
+    ValidationFailed { errors: Vec<FieldError> },
+}
+
+/// Represents a single invalid field of an input.
+#[derive(candid::CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct FieldError {
+    field: String,
+    reason: String,
+}
+
+impl Error {
+    fn not_found(entity: &str, id: impl Into<u64>) -> Self {
+        Error::NotFound {
+            entity: entity.to_string(),
+            id: id.into(),
+        }
+    }
+
+    fn invalid_input(field: &str, reason: &str) -> Self {
+        Error::InvalidInput {
+            field: field.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    fn unauthorized(reason: &str) -> Self {
+        Error::Unauthorized {
+            reason: reason.to_string(),
+        }
+    }
+
+    fn conflict(entity: &str, reason: String) -> Self {
+        Error::Conflict {
+            entity: entity.to_string(),
+            reason,
+        }
+    }
+}
+
+/// Implemented by the typed entity IDs, so that storage helpers can be shared between them.
+trait EntityId: BoundedStorable + Ord + Clone + Copy + Default + From<u64> + Into<u64> {}
+
+/// Defines a typed entity ID that is stored and sent over the wire exactly like a `u64`. The
+/// candid file declares each one as an alias of `nat64`.
+macro_rules! entity_id {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(
+            candid::CandidType, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+        )]
+        struct $name(u64);
+
+        impl EntityId for $name {}
+
+        impl From<$name> for u64 {
+            fn from(id: $name) -> Self {
+                id.0
+            }
+        }
+
+        impl From<u64> for $name {
+            fn from(id: u64) -> Self {
+                $name(id)
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+
+        impl Storable for $name {
+            fn to_bytes(&self) -> Cow<'_, [u8]> {
+                Cow::Owned(self.0.to_be_bytes().to_vec())
+            }
+
+            fn from_bytes(bytes: Cow<[u8]>) -> Self {
+                $name(u64::from_bytes(bytes))
+            }
+        }
+
+        impl BoundedStorable for $name {
+            const MAX_SIZE: u32 = u64::MAX_SIZE;
+            const IS_FIXED_SIZE: bool = true;
+        }
+    };
+}
+
+entity_id!(
+    /// Represents the ID of a student.
+    StudentId
+);
+entity_id!(
+    /// Represents the ID of a teacher.
+    TeacherId
+);
+entity_id!(
+    /// Represents the ID of a course.
+    CourseId
+);
+entity_id!(
+    /// Represents the ID of a classroom.
+    ClassroomId
+);
+entity_id!(
+    /// Represents the ID of a guardian.
+    GuardianId
+);
+
+/// Represents a calendar date.
+#[derive(
+    candid::CandidType, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+struct Date {
+    year: u16,
+    month: u8,
+    day: u8,
+}
+
+impl Date {
+    fn is_leap_year(year: u16) -> bool {
+        (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+    }
+
+    fn days_in_month(year: u16, month: u8) -> u8 {
+        match month {
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+            4 | 6 | 9 | 11 => 30,
+            2 if Self::is_leap_year(year) => 29,
+            2 => 28,
+            _ => 0,
+        }
+    }
+
+    /// Returns whether the date exists in the Gregorian calendar.
+    fn is_valid(&self) -> bool {
+        self.year > 0 && self.day >= 1 && self.day <= Self::days_in_month(self.year, self.month)
+    }
+
+    /// Parses a valid date in the YYYY-MM-DD format.
+    fn parse(text: &str) -> Option<Self> {
+        let mut parts = text.trim().splitn(3, '-');
+        let date = Date {
+            year: parts.next()?.parse().ok()?,
+            month: parts.next()?.parse().ok()?,
+            day: parts.next()?.parse().ok()?,
+        };
+        date.is_valid().then_some(date)
+    }
+
+    /// Returns the number of days since 1970-01-01.
+    fn to_days(self) -> i64 {
+        let year = i64::from(self.year) - i64::from(self.month <= 2);
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let month = i64::from(self.month);
+        let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + i64::from(self.day) - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146_097 + day_of_era - 719_468
+    }
+
+    /// Returns the date a number of days after 1970-01-01.
+    fn from_days(days: i64) -> Self {
+        let days = days + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days - era * 146_097;
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        Date {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+        }
+    }
+
+    fn add_days(self, days: i64) -> Self {
+        Self::from_days(self.to_days() + days)
+    }
+
+    /// Returns the day of the week the date falls on.
+    fn day_of_week(self) -> DayOfWeek {
+        // 1970-01-01 was a Thursday.
+        DayOfWeek::from_index((self.to_days() + 3).rem_euclid(7) as u8)
+    }
+
+    /// Returns the current date in UTC.
+    fn today() -> Self {
+        Self::from_days((time() / NANOS_PER_DAY) as i64)
+    }
+
+    /// Returns the number of whole years from this date to `on`, or None if `on` is earlier.
+    fn years_until(self, on: Date) -> Option<u32> {
+        if on < self {
+            return None;
+        }
+        let mut years = u32::from(on.year - self.year);
+        if (on.month, on.day) < (self.month, self.day) {
+            years -= 1;
+        }
+        Some(years)
+    }
+
+    /// Returns whether the date falls inside the optional inclusive range.
+    fn is_within(&self, from: Option<Date>, to: Option<Date>) -> bool {
+        from.map_or(true, |from| *self >= from) && to.map_or(true, |to| *self <= to)
+    }
+}
+
+/// Number of nanoseconds in a day, the unit of `ic_cdk::api::time`.
+const NANOS_PER_DAY: u64 = 86_400 * 1_000_000_000;
+
+/// Youngest plausible age of a student, in years above their grade level.
+const MIN_AGE_ABOVE_GRADE: u32 = 3;
+
+/// Oldest plausible age of a student, in years above their grade level.
+const MAX_AGE_ABOVE_GRADE: u32 = 10;
+
+/// Represents a day of the week.
+#[derive(candid::CandidType, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+enum DayOfWeek {
+    #[default]
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl DayOfWeek {
+    /// Returns the day for a zero-based index starting on Monday.
+    fn from_index(index: u8) -> Self {
+        match index % 7 {
+            0 => DayOfWeek::Monday,
+            1 => DayOfWeek::Tuesday,
+            2 => DayOfWeek::Wednesday,
+            3 => DayOfWeek::Thursday,
+            4 => DayOfWeek::Friday,
+            5 => DayOfWeek::Saturday,
+            _ => DayOfWeek::Sunday,
+        }
+    }
+}
+
+/// Represents a wall-clock time within a school day.
+#[derive(candid::CandidType, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+struct TimeOfDay {
+    hour: u8,
+    minute: u8,
+}
+
+impl TimeOfDay {
+    fn is_valid(&self) -> bool {
+        self.hour < 24 && self.minute < 60
+    }
+}
+
+/// Represents when and by whom a record was created and last modified.
+#[derive(candid::CandidType, Clone, Copy, Debug, Serialize, Deserialize)]
+struct Metadata {
+    created_at: u64, // Nanoseconds since the UNIX epoch
+    updated_at: u64, // Nanoseconds since the UNIX epoch
+    updated_by: Principal,
+}
+
+impl Default for Metadata {
+    fn default() -> Self {
+        Metadata {
+            created_at: 0,
+            updated_at: 0,
+            updated_by: Principal::anonymous(),
+        }
+    }
+}
+
+impl Metadata {
+    /// Returns the metadata of a record created now by the caller.
+    fn new() -> Self {
+        let now = time();
+        Metadata {
+            created_at: now,
+            updated_at: now,
+            updated_by: caller(),
+        }
+    }
+
+    /// Marks the record as modified now by the caller.
+    fn touch(&mut self) {
+        self.updated_at = time();
+        self.updated_by = caller();
+    }
+}
+
+/// Implemented by records that carry creation and modification metadata.
+trait HasMetadata {
+    fn metadata(&self) -> &Metadata;
+    fn metadata_mut(&mut self) -> &mut Metadata;
+}
+
+/// Represents why, when and by whom a record was archived.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct ArchiveInfo {
+    reason: String,
+    archived_by: Principal,
+    archived_at: u64, // Nanoseconds since the UNIX epoch
+}
+
+/// Represents a deleted record, retained with its archive information until it is purged.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct Archived<T> {
+    record: T,
+    archive: ArchiveInfo,
+}
+
+impl<T: HasMetadata> HasMetadata for Archived<T> {
+    fn metadata(&self) -> &Metadata {
+        self.record.metadata()
+    }
+
+    fn metadata_mut(&mut self) -> &mut Metadata {
+        self.record.metadata_mut()
+    }
+}
+
+/// Represents how a delete treats the records that refer to the deleted one.
+#[derive(candid::CandidType, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+enum DeletePolicy {
+    /// Rejects the delete while any record refers to the deleted one.
+    #[default]
+    Restrict,
+    /// Clears the references, removing the schedule slots that cannot exist without the record.
+    Nullify,
+    /// Like `Nullify`, but also deletes the courses of a deleted teacher.
+    Cascade,
+}
+
+/// Represents a reference to a record of any entity.
+#[derive(candid::CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct RecordRef {
+    entity: String,
+    id: u64,
+}
+
+impl RecordRef {
+    fn new(entity: &str, id: impl Into<u64>) -> Self {
+        RecordRef {
+            entity: entity.to_string(),
+            id: id.into(),
+        }
+    }
+}
+
+/// Represents the records affected by a delete.
+#[derive(candid::CandidType, Clone, Debug, Default, Serialize, Deserialize)]
+struct DeleteSummary {
+    archived: Vec<RecordRef>, // The deleted record, then the records deleted with it
+    updated: Vec<RecordRef>,  // Records whose references to an archived record were cleared
+    removed_slots: Vec<u64>,  // Schedule slots removed with their course or classroom
+}
+
+impl DeleteSummary {
+    fn updated(&mut self, entity: &str, id: impl Into<u64>) {
+        let record = RecordRef::new(entity, id);
+        if !self.updated.contains(&record) {
+            self.updated.push(record);
+        }
+    }
+}
+
+/// Implemented by the records that are archived instead of removed when deleted.
+trait Archivable: BoundedStorable + Clone + HasMetadata + serde::Serialize + Versioned {
+    /// Name of the entity in errors and audit entries.
+    const ENTITY: &'static str;
+}
+
+impl<T: Versioned> Storable for Archived<T> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(migration::encode_archived(self))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        migration::decode_archived(&bytes)
+    }
+}
+
+impl<T: BoundedStorable + Versioned> BoundedStorable for Archived<T> {
+    const MAX_SIZE: u32 = T::MAX_SIZE + 512; // Room for the reason and the archive details
+    const IS_FIXED_SIZE: bool = false;
+}
 
 /// Represents information about a student.
 #[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
 struct Student {
-    id: u64,
+    id: StudentId,
     name: String,
     grade_level: u8,
-    enrolled_courses: Vec<u64>,
+    enrolled_courses: Vec<CourseId>,
     email: String,
-    date_of_birth: String, // Format: YYYY-MM-DD
+    date_of_birth: Date,
     address: String,
-    guardian_details: String,
-    performance_records: Vec<u64>, // IDs of performance/grade records
-    attendance_records: Vec<u64>, // IDs of attendance records
+    metadata: Metadata,
     // Additional student-specific fields
 }
 
+impl HasMetadata for Student {
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn metadata_mut(&mut self) -> &mut Metadata {
+        &mut self.metadata
+    }
+}
+
+impl Archivable for Student {
+    const ENTITY: &'static str = "Student";
+}
+
 impl Storable for Student {
-    fn to_bytes(&self) -> Cow<[u8]> {
-        Cow::Owned(Encode!(self).unwrap())
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(migration::encode(self))
     }
 
     fn from_bytes(bytes: Cow<[u8]>) -> Self {
-        Decode!(bytes.as_ref(), Self).unwrap()
+        migration::decode(&bytes)
     }
 }
 
@@ -43,25 +477,40 @@ impl BoundedStorable for Student {
 /// Represents information about a teacher.
 #[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
 struct Teacher {
-    id: u64,
+    id: TeacherId,
     name: String,
     subject_area: String,
-    assigned_courses: Vec<u64>,
+    assigned_courses: Vec<CourseId>,
     email: String,
     qualifications: String,
-    employment_date: String, // Format: YYYY-MM-DD
+    employment_date: Date,
     address: String,
-    schedule: Vec<u64>, // IDs of scheduled classes or duties
+    schedule: Vec<u64>, // IDs of the schedule slots of the assigned courses
+    metadata: Metadata,
     // Additional teacher-specific fields
 }
 
+impl HasMetadata for Teacher {
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn metadata_mut(&mut self) -> &mut Metadata {
+        &mut self.metadata
+    }
+}
+
+impl Archivable for Teacher {
+    const ENTITY: &'static str = "Teacher";
+}
+
 impl Storable for Teacher {
-    fn to_bytes(&self) -> Cow<[u8]> {
-        Cow::Owned(Encode!(self).unwrap())
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(migration::encode(self))
     }
 
     fn from_bytes(bytes: Cow<[u8]>) -> Self {
-        Decode!(bytes.as_ref(), Self).unwrap()
+        migration::decode(&bytes)
     }
 }
 
@@ -73,24 +522,38 @@ impl BoundedStorable for Teacher {
 /// Represents information about a course.
 #[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
 struct Course {
-    id: u64,
+    id: CourseId,
     name: String,
     description: String,
-    teacher_id: u64,
-    student_ids: Vec<u64>,
-    schedule: String, // Course schedule details
+    teacher_id: Option<TeacherId>, // None while the course has no teacher
+    student_ids: Vec<StudentId>,
     syllabus: String,
     course_materials: Vec<String>, // URLs or IDs of course materials
+    metadata: Metadata,
     // Additional course-specific fields
 }
 
+impl HasMetadata for Course {
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn metadata_mut(&mut self) -> &mut Metadata {
+        &mut self.metadata
+    }
+}
+
+impl Archivable for Course {
+    const ENTITY: &'static str = "Course";
+}
+
 impl Storable for Course {
-    fn to_bytes(&self) -> Cow<[u8]> {
-        Cow::Owned(Encode!(self).unwrap())
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(migration::encode(self))
     }
 
     fn from_bytes(bytes: Cow<[u8]>) -> Self {
-        Decode!(bytes.as_ref(), Self).unwrap()
+        migration::decode(&bytes)
     }
 }
 
@@ -102,21 +565,37 @@ impl BoundedStorable for Course {
 /// Represents information about a classroom.
 #[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
 struct Classroom {
-    id: u64,
+    id: ClassroomId,
     name: String,
     location: String,
     capacity: u32,
-    current_course_id: u64,
+    current_course_id: Option<CourseId>,
     equipment: Vec<String>, // List of classroom equipment/resources
+    metadata: Metadata,
     // Additional classroom-specific fields
 }
+
+impl HasMetadata for Classroom {
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn metadata_mut(&mut self) -> &mut Metadata {
+        &mut self.metadata
+    }
+}
+
+impl Archivable for Classroom {
+    const ENTITY: &'static str = "Classroom";
+}
+
 impl Storable for Classroom {
-    fn to_bytes(&self) -> Cow<[u8]> {
-        Cow::Owned(Encode!(self).unwrap())
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(migration::encode(self))
     }
 
     fn from_bytes(bytes: Cow<[u8]>) -> Self {
-        Decode!(bytes.as_ref(), Self).unwrap()
+        migration::decode(&bytes)
     }
 }
 
@@ -131,25 +610,11 @@ struct StudentPayload {
     name: String,
     grade_level: u8,
     email: String,
-    date_of_birth: String,
+    date_of_birth: Date,
     address: String,
-    guardian_details: String,
     // Additional student-specific payload fields
 }
 
-impl Default for StudentPayload {
-    fn default() -> Self {
-        StudentPayload {
-            name: String::default(),
-            grade_level: 0,
-            email: String::default(),
-            date_of_birth: String::default(),
-            address: String::default(),
-            guardian_details: String::default(),
-        }
-    }
-}
-
 /// Represents payload for adding a teacher.
 #[derive(candid::CandidType, Serialize, Deserialize, Default)]
 struct TeacherPayload {
@@ -157,420 +622,5529 @@ struct TeacherPayload {
     subject_area: String,
     email: String,
     qualifications: String,
-    employment_date: String,
+    employment_date: Date,
     address: String,
-    assigned_courses: Vec<u64>, // Newly added field
-    schedule: Vec<u64>, // Newly added field
     // Additional teacher-specific payload fields
 }
 
-impl Default for TeacherPayload {
-    fn default() -> Self {
-        TeacherPayload {
-            name: String::default(),
-            subject_area: String::default(),
-            email: String::default(),
-            qualifications: String::default(),
-            employment_date: String::default(),
-            address: String::default(),
-            assigned_courses: Vec::default(),
-            schedule: Vec::default(),
-        }
-    }
-}
-
 /// Represents payload for adding a course.
 #[derive(candid::CandidType, Serialize, Deserialize, Default)]
 struct CoursePayload {
     name: String,
     description: String,
-    teacher_id: u64,
+    teacher_id: Option<TeacherId>,
     // Additional course-specific payload fields
 }
 
-impl Default for CoursePayload {
-    fn default() -> Self {
-        CoursePayload {
-            name: String::default(),
-            description: String::default(),
-            teacher_id: 0,
-        }
-    }
-}
-
 /// Represents payload for adding a classroom.
 #[derive(candid::CandidType, Serialize, Deserialize, Default)]
 struct ClassroomPayload {
     name: String,
     location: String,
     capacity: u32,
-    current_course_id: u64,
+    current_course_id: Option<CourseId>,
     // Additional classroom-specific payload fields
 }
 
-impl Default for ClassroomPayload {
-    fn default() -> Self {
-        ClassroomPayload {
-            name: String::default(),
-            location: String::default(),
-            capacity: 0,
-            current_course_id: 0,
+/// Represents a partial update of a student; only the provided fields change.
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+struct StudentPatch {
+    name: Option<String>,
+    grade_level: Option<u8>,
+    email: Option<String>,
+    date_of_birth: Option<Date>,
+    address: Option<String>,
+}
+
+impl StudentPatch {
+    fn apply_to(self, payload: &mut StudentPayload) {
+        if let Some(name) = self.name {
+            payload.name = name;
+        }
+        if let Some(grade_level) = self.grade_level {
+            payload.grade_level = grade_level;
+        }
+        if let Some(email) = self.email {
+            payload.email = email;
+        }
+        if let Some(date_of_birth) = self.date_of_birth {
+            payload.date_of_birth = date_of_birth;
+        }
+        if let Some(address) = self.address {
+            payload.address = address;
         }
     }
 }
 
-thread_local! {
-    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
-        MemoryManager::init(DefaultMemoryImpl::default())
-    );
+impl From<&Student> for StudentPayload {
+    fn from(student: &Student) -> Self {
+        StudentPayload {
+            name: student.name.clone(),
+            grade_level: student.grade_level,
+            email: student.email.clone(),
+            date_of_birth: student.date_of_birth,
+            address: student.address.clone(),
+        }
+    }
+}
 
-    static ID_COUNTER: RefCell<IdCell> = RefCell::new(
-        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(0))), 0)
-            .expect("Cannot create a counter")
-    );
+/// Represents a partial update of a teacher; only the provided fields change.
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+struct TeacherPatch {
+    name: Option<String>,
+    subject_area: Option<String>,
+    email: Option<String>,
+    qualifications: Option<String>,
+    employment_date: Option<Date>,
+    address: Option<String>,
+}
 
-    static STUDENT_STORAGE: RefCell<StableBTreeMap<u64, Student, Memory>> =
-        RefCell::new(StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1)))
-    ));
+impl TeacherPatch {
+    fn apply_to(self, payload: &mut TeacherPayload) {
+        if let Some(name) = self.name {
+            payload.name = name;
+        }
+        if let Some(subject_area) = self.subject_area {
+            payload.subject_area = subject_area;
+        }
+        if let Some(email) = self.email {
+            payload.email = email;
+        }
+        if let Some(qualifications) = self.qualifications {
+            payload.qualifications = qualifications;
+        }
+        if let Some(employment_date) = self.employment_date {
+            payload.employment_date = employment_date;
+        }
+        if let Some(address) = self.address {
+            payload.address = address;
+        }
+    }
+}
 
-    static TEACHER_STORAGE: RefCell<StableBTreeMap<u64, Teacher, Memory>> =
-        RefCell::new(StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2)))
-    ));
+impl From<&Teacher> for TeacherPayload {
+    fn from(teacher: &Teacher) -> Self {
+        TeacherPayload {
+            name: teacher.name.clone(),
+            subject_area: teacher.subject_area.clone(),
+            email: teacher.email.clone(),
+            qualifications: teacher.qualifications.clone(),
+            employment_date: teacher.employment_date,
+            address: teacher.address.clone(),
+        }
+    }
+}
 
-    static COURSE_STORAGE: RefCell<StableBTreeMap<u64, Course, Memory>> =
-        RefCell::new(StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3)))
-    ));
+/// Represents a partial update of a course; only the provided fields change.
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+struct CoursePatch {
+    name: Option<String>,
+    description: Option<String>,
+    teacher_id: Option<Option<TeacherId>>, // Some(None) removes the teacher
+}
 
-    static CLASSROOM_STORAGE: RefCell<StableBTreeMap<u64, Classroom, Memory>> =
-        RefCell::new(StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(4)))
-    ));
+impl CoursePatch {
+    fn apply_to(self, payload: &mut CoursePayload) {
+        if let Some(name) = self.name {
+            payload.name = name;
+        }
+        if let Some(description) = self.description {
+            payload.description = description;
+        }
+        if let Some(teacher_id) = self.teacher_id {
+            payload.teacher_id = teacher_id;
+        }
+    }
 }
 
-/// Adds a new student with the provided payload.
-#[ic_cdk::update]
-fn add_student(payload: StudentPayload) -> Result<Student, String> {
-    // Validation logic (basic example, add more as needed)
-    if payload.name.is_empty() || payload.email.is_empty() {
-        return Err("Name and email are required fields".to_string());
+impl From<&Course> for CoursePayload {
+    fn from(course: &Course) -> Self {
+        CoursePayload {
+            name: course.name.clone(),
+            description: course.description.clone(),
+            teacher_id: course.teacher_id,
+        }
     }
+}
 
-    let id = ID_COUNTER.with(|counter| {
-        let current_value = *counter.borrow().get();
-        let _ = counter.borrow_mut().set(current_value + 1);
-        current_value + 1
-    });
+/// Represents a partial update of a classroom; only the provided fields change.
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+struct ClassroomPatch {
+    name: Option<String>,
+    location: Option<String>,
+    capacity: Option<u32>,
+    current_course_id: Option<Option<CourseId>>, // Some(None) removes the current course
+}
 
-    let student = Student {
-        id,
-        name: payload.name,
-        grade_level: payload.grade_level,
-        enrolled_courses: Vec::new(),
-        email: payload.email,
-        date_of_birth: payload.date_of_birth,
-        address: payload.address,
-        guardian_details: payload.guardian_details,
-        performance_records: Vec::new(),
-        attendance_records: Vec::new(),
-    };
-
-    STUDENT_STORAGE.with(|storage| {
-        storage.borrow_mut().insert(id, student.clone());
-    });
-
-    Ok(student)
+impl ClassroomPatch {
+    fn apply_to(self, payload: &mut ClassroomPayload) {
+        if let Some(name) = self.name {
+            payload.name = name;
+        }
+        if let Some(location) = self.location {
+            payload.location = location;
+        }
+        if let Some(capacity) = self.capacity {
+            payload.capacity = capacity;
+        }
+        if let Some(current_course_id) = self.current_course_id {
+            payload.current_course_id = current_course_id;
+        }
+    }
 }
 
-/// Retrieves information about a student based on the provided ID.
-#[ic_cdk::query]
-fn get_student(id: u64) -> Result<Student, String> {
-    STUDENT_STORAGE.with(|storage| {
-        match storage.borrow().get(&id) {
-            Some(student) => Ok(student.clone()),
-            None => Err(format!("Student with ID {} not found", id)),
+impl From<&Classroom> for ClassroomPayload {
+    fn from(classroom: &Classroom) -> Self {
+        ClassroomPayload {
+            name: classroom.name.clone(),
+            location: classroom.location.clone(),
+            capacity: classroom.capacity,
+            current_course_id: classroom.current_course_id,
         }
-    })
+    }
 }
 
-/// Updates information about a student based on the provided ID and payload.
-#[ic_cdk::update]
-fn update_student(id: u64, payload: StudentPayload) -> Result<Student, String> {
-    STUDENT_STORAGE.with(|storage| {
-        let mut storage = storage.borrow_mut();
-        if let Some(existing_student) = storage.get(&id) {
-            // Clone the existing student to make a mutable copy
-            let mut updated_student = existing_student.clone();
+/// Represents a normalized string key of a secondary index.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct IndexKey(String);
 
-            // Update the fields
-            updated_student.name = payload.name;
-            updated_student.grade_level = payload.grade_level;
-            updated_student.email = payload.email;
-            updated_student.date_of_birth = payload.date_of_birth;
-            updated_student.address = payload.address;
-            updated_student.guardian_details = payload.guardian_details;
+impl IndexKey {
+    /// Creates a key, truncated on a character boundary to fit within `MAX_SIZE`.
+    fn new(value: &str) -> Self {
+        let mut end = value.len().min(Self::MAX_SIZE as usize);
+        while !value.is_char_boundary(end) {
+            end -= 1;
+        }
+        IndexKey(value[..end].to_string())
+    }
 
-            // Re-insert the updated student back into the storage
-            storage.insert(id, updated_student.clone());
+    /// Creates the key of an email address.
+    fn email(email: &str) -> Self {
+        Self::new(&email.trim().to_lowercase())
+    }
 
-            Ok(updated_student)
-        } else {
-            Err(format!("Student with ID {} not found", id))
-        }
-    })
-}
+    /// Creates the keys of a name: the normalized name and each suffix starting at a word,
+    /// so that a prefix search matches any of the words of the name.
+    fn name(name: &str) -> Vec<Self> {
+        let words: Vec<String> = name.split_whitespace().map(str::to_lowercase).collect();
+        (0..words.len()).map(|i| Self::new(&words[i..].join(" "))).collect()
+    }
 
-/// Deletes a student based on the provided ID.
-#[ic_cdk::update]
-fn delete_student(id: u64) -> Result<(), String> {
-    STUDENT_STORAGE.with(|storage| {
-        if storage.borrow_mut().remove(&id).is_some() {
-            Ok(())
-        } else {
-            Err(format!("Student with ID {} not found", id))
-        }
-    })
+    /// Creates the key of a name prefix to search for.
+    fn name_prefix(prefix: &str) -> Self {
+        let words: Vec<String> = prefix.split_whitespace().map(str::to_lowercase).collect();
+        Self::new(&words.join(" "))
+    }
 }
 
-/// Adds a new teacher with the provided payload.
-#[ic_cdk::update]
-fn add_teacher(payload: TeacherPayload) -> Result<Teacher, String> {
-    // Validation logic (basic example, add more as needed)
-    if payload.name.is_empty() || payload.email.is_empty() {
-        return Err("Name and email are required fields".to_string());
+impl Storable for IndexKey {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.0.as_bytes())
     }
 
-    let id = ID_COUNTER.with(|counter| {
-        let current_value = *counter.borrow().get();
-        let _ = counter.borrow_mut().set(current_value + 1);
-        current_value + 1
-    });
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        IndexKey(String::from_utf8(bytes.into_owned()).unwrap())
+    }
+}
 
-    let teacher = Teacher {
-        id,
-        name: payload.name,
-        subject_area: payload.subject_area,
-        assigned_courses: Vec::new(),
-        email: payload.email,
-        qualifications: payload.qualifications,
-        employment_date: payload.employment_date,
-        address: payload.address,
-        schedule: Vec::new(),
-    };
+impl BoundedStorable for IndexKey {
+    const MAX_SIZE: u32 = 256;
+    const IS_FIXED_SIZE: bool = false;
+}
 
-    TEACHER_STORAGE.with(|storage| {
-        storage.borrow_mut().insert(id, teacher.clone());
-    });
+/// Represents an email address shared by several students or teachers.
+#[derive(candid::CandidType, Serialize, Deserialize)]
+struct DuplicateEmail {
+    email: String, // Normalized form of the address
+    student_ids: Vec<StudentId>,
+    teacher_ids: Vec<TeacherId>,
+}
 
-    Ok(teacher)
+/// Maximum number of records returned by a name search.
+const MAX_SEARCH_RESULTS: usize = 50;
+
+/// Represents a request for one page of a listing, ordered by ID.
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+struct PageRequest {
+    cursor: Option<u64>, // The `next_cursor` of the previous page; None for the first page
+    limit: Option<u32>,
 }
 
-/// Retrieves information about a teacher based on the provided ID.
-#[ic_cdk::query]
-fn get_teacher(id: u64) -> Result<Teacher, String> {
-    TEACHER_STORAGE.with(|storage| {
-        match storage.borrow().get(&id) {
-            Some(teacher) => Ok(teacher.clone()),
-            None => Err(format!("Teacher with ID {} not found", id)),
-        }
-    })
+/// Represents one page of a listing.
+#[derive(candid::CandidType, Serialize, Deserialize)]
+struct Page<T> {
+    items: Vec<T>,
+    next_cursor: Option<u64>, // None on the last page
 }
 
-/// Updates information about a teacher based on the provided ID and payload.
-#[ic_cdk::update]
-fn update_teacher(id: u64, payload: TeacherPayload) -> Result<Teacher, String> {
-    TEACHER_STORAGE.with(|storage| {
-        let mut storage = storage.borrow_mut();
-        if let Some(existing_teacher) = storage.get(&id) {
-            // Clone the existing teacher to make a mutable copy
-            let mut updated_teacher = existing_teacher.clone();
+/// Number of items returned when a page request has no limit.
+const DEFAULT_PAGE_SIZE: u32 = 20;
 
-            // Update the fields
-            updated_teacher.name = payload.name;
-            updated_teacher.subject_area = payload.subject_area;
-            updated_teacher.assigned_courses = payload.assigned_courses;
-            updated_teacher.email = payload.email;
-            updated_teacher.qualifications = payload.qualifications;
-            updated_teacher.employment_date = payload.employment_date;
-            updated_teacher.address = payload.address;
-            updated_teacher.schedule = payload.schedule;
+/// Maximum number of items returned in a single page.
+const MAX_PAGE_SIZE: u32 = 100;
 
-            // Re-insert the updated teacher back into the storage
-            storage.insert(id, updated_teacher.clone());
+/// Maximum number of students on a course roster, keeping a course within its `MAX_SIZE`.
+const MAX_STUDENTS_PER_COURSE: usize = 100;
 
-            Ok(updated_teacher)
-        } else {
-            Err(format!("Teacher with ID {} not found", id))
-        }
-    })
+/// Maximum number of courses per student, keeping a student within its `MAX_SIZE`.
+const MAX_COURSES_PER_STUDENT: usize = 30;
+
+/// Represents whether a student attended a session.
+#[derive(candid::CandidType, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+enum AttendanceStatus {
+    #[default]
+    Present,
+    Absent,
+    Late,
+    Excused,
 }
 
-/// Deletes a teacher based on the provided ID.
-#[ic_cdk::update]
-fn delete_teacher(id: u64) -> Result<(), String> {
-    TEACHER_STORAGE.with(|storage| {
-        if storage.borrow_mut().remove(&id).is_some() {
-            Ok(())
-        } else {
-            Err(format!("Teacher with ID {} not found", id))
-        }
-    })
+/// Represents the attendance of a student at a single course session.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct AttendanceRecord {
+    id: u64,
+    student_id: StudentId,
+    course_id: CourseId,
+    session_date: Date,
+    status: AttendanceStatus,
+    note: String,
+    recorded_by: Principal,
+    recorded_at: u64, // Nanoseconds since the UNIX epoch
 }
 
-/// Adds a new course with the provided payload.
-#[ic_cdk::update]
-fn add_course(payload: CoursePayload) -> Result<Course, String> {
-    // Validation logic (basic example, add more as needed)
-    if payload.name.is_empty() || payload.description.is_empty() {
-        return Err("Name and description are required fields".to_string());
+impl Storable for AttendanceRecord {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(migration::encode(self))
     }
 
-    let id = ID_COUNTER.with(|counter| {
-        let current_value = *counter.borrow().get();
-        let _ = counter.borrow_mut().set(current_value + 1);
-        current_value + 1
-    });
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        migration::decode(&bytes)
+    }
+}
 
-    let course = Course {
-        id,
-        name: payload.name,
-        description: payload.description,
-        teacher_id: payload.teacher_id,
-        student_ids: Vec::new(),
-        schedule: String::new(), // Initial empty schedule
-        syllabus: String::new(), // Initial empty syllabus
-        course_materials: Vec::new(), // Initial empty course materials
-    };
+impl BoundedStorable for AttendanceRecord {
+    const MAX_SIZE: u32 = 1024;
+    const IS_FIXED_SIZE: bool = false;
+}
 
-    COURSE_STORAGE.with(|storage| {
-        storage.borrow_mut().insert(id, course.clone());
-    });
+/// Represents payload for marking attendance.
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+struct AttendancePayload {
+    student_id: StudentId,
+    course_id: CourseId,
+    session_date: Date,
+    status: AttendanceStatus,
+    note: String,
+}
 
-    Ok(course)
+/// Represents payload for correcting an existing attendance record.
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+struct AttendanceCorrection {
+    status: AttendanceStatus,
+    note: String,
 }
 
-/// Retrieves information about a course based on the provided ID.
-#[ic_cdk::query]
-fn get_course(id: u64) -> Result<Course, String> {
-    COURSE_STORAGE.with(|storage| {
-        match storage.borrow().get(&id) {
-            Some(course) => Ok(course.clone()),
-            None => Err(format!("Course with ID {} not found", id)),
-        }
-    })
+/// Represents a graded assessment of a student in a course.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct PerformanceRecord {
+    id: u64,
+    student_id: StudentId,
+    course_id: CourseId,
+    assessment_name: String,
+    score: f64,
+    max_score: f64,
+    weight: f64, // Relative weight of the assessment within the course
+    graded_by: TeacherId, // ID of the teacher of the course
+    graded_at: u64, // Nanoseconds since the UNIX epoch
 }
 
-/// Updates information about a course based on the provided ID and payload.
-#[ic_cdk::update]
-fn update_course(id: u64, payload: CoursePayload) -> Result<Course, String> {
-    COURSE_STORAGE.with(|storage| {
-        let mut storage = storage.borrow_mut();
-        if let Some(existing_course) = storage.get(&id) {
-            let mut updated_course = existing_course.clone();
+impl Storable for PerformanceRecord {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(migration::encode(self))
+    }
 
-            updated_course.name = payload.name;
-            updated_course.description = payload.description;
-            updated_course.teacher_id = payload.teacher_id;
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        migration::decode(&bytes)
+    }
+}
 
-            // Note: Student IDs, schedule, syllabus, and materials are not updated here
-            storage.insert(id, updated_course.clone());
+impl BoundedStorable for PerformanceRecord {
+    const MAX_SIZE: u32 = 1024;
+    const IS_FIXED_SIZE: bool = false;
+}
 
-            Ok(updated_course)
-        } else {
-            Err(format!("Course with ID {} not found", id))
-        }
-    })
+/// Represents payload for recording a grade.
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+struct GradePayload {
+    student_id: StudentId,
+    course_id: CourseId,
+    teacher_id: TeacherId,
+    assessment_name: String,
+    score: f64,
+    max_score: f64,
+    weight: f64,
 }
 
-/// Deletes a course based on the provided ID.
-#[ic_cdk::update]
-fn delete_course(id: u64) -> Result<(), String> {
-    COURSE_STORAGE.with(|storage| {
-        if storage.borrow_mut().remove(&id).is_some() {
-            Ok(())
-        } else {
-            Err(format!("Course with ID {} not found", id))
+/// Represents payload for amending an existing grade.
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+struct GradeAmendment {
+    teacher_id: TeacherId,
+    score: f64,
+    max_score: f64,
+    weight: f64,
+}
+
+/// Represents a percentage band of the grading scale.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct GradeBand {
+    min_percentage: f64, // Inclusive lower bound of the band
+    letter: String,
+    grade_points: f64,
+}
+
+/// Represents the mapping from course percentages to letter grades and grade points.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct GradingScale {
+    bands: Vec<GradeBand>,
+}
+
+impl Default for GradingScale {
+    fn default() -> Self {
+        let band = |min_percentage: f64, letter: &str, grade_points: f64| GradeBand {
+            min_percentage,
+            letter: letter.to_string(),
+            grade_points,
+        };
+        GradingScale {
+            bands: vec![
+                band(90.0, "A", 4.0),
+                band(80.0, "B", 3.0),
+                band(70.0, "C", 2.0),
+                band(60.0, "D", 1.0),
+                band(0.0, "F", 0.0),
+            ],
         }
-    })
+    }
 }
 
-/// Adds a new classroom with the provided payload.
-#[ic_cdk::update]
-fn add_classroom(payload: ClassroomPayload) -> Result<Classroom, String> {
-    // Validation logic (basic example, add more as needed)
-    if payload.name.is_empty() || payload.location.is_empty() {
-        return Err("Name and location are required fields".to_string());
+impl GradingScale {
+    /// Returns the band with the highest lower bound the percentage reaches.
+    fn band_for(&self, percentage: f64) -> Option<&GradeBand> {
+        self.bands
+            .iter()
+            .filter(|band| percentage >= band.min_percentage)
+            .max_by(|a, b| a.min_percentage.total_cmp(&b.min_percentage))
     }
+}
 
-    let id = ID_COUNTER.with(|counter| {
-        let current_value = *counter.borrow().get();
-        let _ = counter.borrow_mut().set(current_value + 1);
-        current_value + 1
-    });
+impl Storable for GradingScale {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(migration::encode(self))
+    }
 
-    let classroom = Classroom {
-        id,
-        name: payload.name,
-        location: payload.location,
-        capacity: payload.capacity,
-        current_course_id: payload.current_course_id,
-        equipment: Vec::new(), // Initial empty equipment list
-    };
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        migration::decode(&bytes)
+    }
+}
 
-    CLASSROOM_STORAGE.with(|storage| {
-        storage.borrow_mut().insert(id, classroom.clone());
-    });
+/// Represents a student's weighted average in a course.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct CourseAverage {
+    student_id: StudentId,
+    course_id: CourseId,
+    percentage: f64,
+    letter: String,
+    grade_points: f64,
+    assessment_count: u32,
+}
 
-    Ok(classroom)
+/// Represents a student's grade point average across their enrolled courses.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct Gpa {
+    student_id: StudentId,
+    gpa: Option<f64>, // None until at least one enrolled course is graded
+    course_averages: Vec<CourseAverage>,
 }
 
-/// Retrieves information about a classroom based on the provided ID.
-#[ic_cdk::query]
-fn get_classroom(id: u64) -> Result<Classroom, String> {
-    CLASSROOM_STORAGE.with(|storage| {
-        match storage.borrow().get(&id) {
-            Some(classroom) => Ok(classroom.clone()),
-            None => Err(format!("Classroom with ID {} not found", id)),
+/// Represents how often a schedule slot repeats.
+#[derive(candid::CandidType, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+enum Recurrence {
+    #[default]
+    Weekly,
+    Fortnightly, // Every other week, counted from the effective start date
+    Once,        // A single session on the effective start date
+}
+
+/// Represents a recurring session of a course in a classroom.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct ScheduleSlot {
+    id: u64,
+    course_id: CourseId,
+    day_of_week: DayOfWeek,
+    start_time: TimeOfDay,
+    end_time: TimeOfDay,
+    classroom_id: ClassroomId,
+    recurrence: Recurrence,
+    effective_from: Date,
+    effective_until: Option<Date>, // Inclusive; None while the slot runs indefinitely
+}
+
+impl ScheduleSlot {
+    /// Returns whether a session of the slot takes place on the date.
+    fn occurs_on(&self, date: Date) -> bool {
+        if date.day_of_week() != self.day_of_week
+            || !date.is_within(Some(self.effective_from), self.effective_until)
+        {
+            return false;
         }
-    })
+        match self.recurrence {
+            Recurrence::Weekly => true,
+            Recurrence::Fortnightly => ((date.to_days() - self.effective_from.to_days()) / 7) % 2 == 0,
+            Recurrence::Once => date == self.effective_from,
+        }
+    }
+
+    /// Returns whether the two slots have a session at overlapping times on some date.
+    fn conflicts_with(&self, other: &ScheduleSlot) -> bool {
+        if self.day_of_week != other.day_of_week
+            || self.start_time >= other.end_time
+            || other.start_time >= self.end_time
+        {
+            return false;
+        }
+
+        let start = self.effective_from.max(other.effective_from);
+        let end = match (self.effective_until, other.effective_until) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        // Every recurrence repeats within two weeks, so a shared session shows up in the
+        // first fourteen days of the common effective range if it exists at all.
+        (0..14)
+            .map(|offset| start.add_days(offset))
+            .take_while(|date| end.map_or(true, |end| *date <= end))
+            .any(|date| self.occurs_on(date) && other.occurs_on(date))
+    }
 }
 
-/// Updates information about a classroom based on the provided ID and payload.
-#[ic_cdk::update]
-fn update_classroom(id: u64, payload: ClassroomPayload) -> Result<Classroom, String> {
-    CLASSROOM_STORAGE.with(|storage| {
-        let mut storage = storage.borrow_mut();
-        if let Some(existing_classroom) = storage.get(&id) {
-            let mut updated_classroom = existing_classroom.clone();
+impl Storable for ScheduleSlot {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(migration::encode(self))
+    }
 
-            updated_classroom.name = payload.name;
-            updated_classroom.location = payload.location;
-            updated_classroom.capacity = payload.capacity;
-            updated_classroom.current_course_id = payload.current_course_id;
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        migration::decode(&bytes)
+    }
+}
 
-            // Equipment is not updated here
-            storage.insert(id, updated_classroom.clone());
+impl BoundedStorable for ScheduleSlot {
+    const MAX_SIZE: u32 = 256;
+    const IS_FIXED_SIZE: bool = false;
+}
 
-            Ok(updated_classroom)
-        } else {
-            Err(format!("Classroom with ID {} not found", id))
-        }
-    })
+/// Represents payload for adding a schedule slot to a course.
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+struct ScheduleSlotPayload {
+    day_of_week: DayOfWeek,
+    start_time: TimeOfDay,
+    end_time: TimeOfDay,
+    classroom_id: ClassroomId,
+    recurrence: Recurrence,
+    effective_from: Date,
+    effective_until: Option<Date>,
 }
 
-/// Deletes a classroom based on the provided ID.
-#[ic_cdk::update]
-fn delete_classroom(id: u64) -> Result<(), String> {
-    CLASSROOM_STORAGE.with(|storage| {
-        if storage.borrow_mut().remove(&id).is_some() {
-            Ok(())
-        } else {
-            Err(format!("Classroom with ID {} not found", id))
-        }
-    })
+/// Represents a free window of a classroom within the school day.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct AvailabilityWindow {
+    date: Date,
+    start_time: TimeOfDay,
+    end_time: TimeOfDay,
 }
 
-// need this to generate candid
-ic_cdk::export_candid!();
+/// Represents a single session in a teacher's timetable.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct TimetableEntry {
+    date: Date,
+    slot: ScheduleSlot,
+}
+
+/// Represents how a guardian is related to a student.
+#[derive(candid::CandidType, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+enum Relationship {
+    Parent,
+    Grandparent,
+    Sibling,
+    LegalGuardian,
+    #[default]
+    Other,
+}
+
+/// Represents information about a guardian of one or more students.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct Guardian {
+    id: GuardianId,
+    name: String,
+    relationship: Relationship,
+    phone: String,
+    email: String,
+    principal: Option<Principal>, // Identity the guardian signs in with, if any
+    has_custody: bool,
+    can_pick_up: bool, // Whether the guardian may collect the students from school
+    notes: String,
+    metadata: Metadata,
+}
+
+impl HasMetadata for Guardian {
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn metadata_mut(&mut self) -> &mut Metadata {
+        &mut self.metadata
+    }
+}
+
+impl Archivable for Guardian {
+    const ENTITY: &'static str = "Guardian";
+}
+
+impl Storable for Guardian {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(migration::encode(self))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        migration::decode(&bytes)
+    }
+}
+
+impl BoundedStorable for Guardian {
+    const MAX_SIZE: u32 = 2048;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+/// Represents payload for adding a guardian.
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+struct GuardianPayload {
+    name: String,
+    relationship: Relationship,
+    phone: String,
+    email: String,
+    principal: Option<Principal>,
+    has_custody: bool,
+    can_pick_up: bool,
+    notes: String,
+}
+
+/// Represents a partial update of a guardian; only the provided fields change.
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+struct GuardianPatch {
+    name: Option<String>,
+    relationship: Option<Relationship>,
+    phone: Option<String>,
+    email: Option<String>,
+    principal: Option<Option<Principal>>, // Some(None) removes the principal
+    has_custody: Option<bool>,
+    can_pick_up: Option<bool>,
+    notes: Option<String>,
+}
+
+impl GuardianPatch {
+    fn apply_to(self, payload: &mut GuardianPayload) {
+        if let Some(name) = self.name {
+            payload.name = name;
+        }
+        if let Some(relationship) = self.relationship {
+            payload.relationship = relationship;
+        }
+        if let Some(phone) = self.phone {
+            payload.phone = phone;
+        }
+        if let Some(email) = self.email {
+            payload.email = email;
+        }
+        if let Some(principal) = self.principal {
+            payload.principal = principal;
+        }
+        if let Some(has_custody) = self.has_custody {
+            payload.has_custody = has_custody;
+        }
+        if let Some(can_pick_up) = self.can_pick_up {
+            payload.can_pick_up = can_pick_up;
+        }
+        if let Some(notes) = self.notes {
+            payload.notes = notes;
+        }
+    }
+}
+
+impl From<&Guardian> for GuardianPayload {
+    fn from(guardian: &Guardian) -> Self {
+        GuardianPayload {
+            name: guardian.name.clone(),
+            relationship: guardian.relationship,
+            phone: guardian.phone.clone(),
+            email: guardian.email.clone(),
+            principal: guardian.principal,
+            has_custody: guardian.has_custody,
+            can_pick_up: guardian.can_pick_up,
+            notes: guardian.notes.clone(),
+        }
+    }
+}
+
+/// Represents the free-text guardian details of a student stored before guardians were
+/// separate records. Decodes from the bytes of a stored student, with no details if the student
+/// is in another layout.
+#[derive(candid::CandidType, Deserialize)]
+struct LegacyGuardianDetails {
+    guardian_details: Option<String>,
+}
+
+impl Storable for LegacyGuardianDetails {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        // Students are enveloped only once written without the details.
+        if migration::is_enveloped(&bytes) {
+            return LegacyGuardianDetails { guardian_details: None };
+        }
+        Decode!(bytes.as_ref(), Self).unwrap_or(LegacyGuardianDetails { guardian_details: None })
+    }
+}
+
+impl BoundedStorable for LegacyGuardianDetails {
+    const MAX_SIZE: u32 = Student::MAX_SIZE;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+/// Represents the free-text dates of birth of students and employment dates of teachers stored
+/// while dates were free text.
+#[derive(candid::CandidType, Deserialize, Default)]
+struct LegacyTextDates {
+    date_of_birth: Option<String>,
+    employment_date: Option<String>,
+}
+
+/// Represents the free-text date of a stored `T`, a student or a teacher. Decodes from the bytes
+/// of a stored `T`, with no date if the record is in another layout.
+struct LegacyTextDate<T> {
+    dates: LegacyTextDates,
+    record: PhantomData<T>,
+}
+
+impl<T> LegacyTextDate<T> {
+    fn text(self) -> Option<String> {
+        self.dates.date_of_birth.or(self.dates.employment_date)
+    }
+}
+
+impl<T> Storable for LegacyTextDate<T> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(&self.dates).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        // Records are enveloped only once written with calendar dates.
+        let dates = if migration::is_enveloped(&bytes) {
+            LegacyTextDates::default()
+        } else {
+            Decode!(bytes.as_ref(), LegacyTextDates).unwrap_or_default()
+        };
+        LegacyTextDate { dates, record: PhantomData }
+    }
+}
+
+impl<T: BoundedStorable> BoundedStorable for LegacyTextDate<T> {
+    const MAX_SIZE: u32 = T::MAX_SIZE;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+/// Maximum number of guardians linked to a student.
+const MAX_GUARDIANS_PER_STUDENT: usize = 10;
+
+/// Represents a role granted to a principal.
+#[derive(candid::CandidType, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+enum Role {
+    Admin,
+    Registrar,
+    Teacher { teacher_id: TeacherId },
+    Student { student_id: StudentId },
+    Guardian { guardian_id: GuardianId },
+}
+
+/// Represents the roles granted to a single principal.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct RoleSet {
+    roles: Vec<Role>,
+}
+
+impl Storable for RoleSet {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(migration::encode(self))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        migration::decode(&bytes)
+    }
+}
+
+impl BoundedStorable for RoleSet {
+    const MAX_SIZE: u32 = 512;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+/// Decodes from the bytes of a stored role set, with no roles if they are in another layout.
+impl Storable for migration::RoleSetV1 {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        if migration::is_enveloped(&bytes) {
+            return migration::RoleSetV1::default();
+        }
+        Decode!(bytes.as_ref(), Self).unwrap_or_default()
+    }
+}
+
+impl BoundedStorable for migration::RoleSetV1 {
+    const MAX_SIZE: u32 = RoleSet::MAX_SIZE;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+/// Represents a principal as a stable map key.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct PrincipalKey(Principal);
+
+impl Storable for PrincipalKey {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.0.as_slice())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        PrincipalKey(Principal::from_slice(bytes.as_ref()))
+    }
+}
+
+impl BoundedStorable for PrincipalKey {
+    const MAX_SIZE: u32 = 29; // The maximum length of a principal
+    const IS_FIXED_SIZE: bool = false;
+}
+
+/// Maximum number of roles granted to a principal, keeping a role set within its `MAX_SIZE`.
+const MAX_ROLES_PER_PRINCIPAL: usize = 16;
+
+/// Represents one mutation in the audit log.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct AuditEntry {
+    id: u64, // Position in the audit log
+    caller: Principal,
+    timestamp: u64, // Nanoseconds since the UNIX epoch
+    endpoint: String,
+    entity: String,
+    entity_id: Option<u64>, // None for entities without an ID, such as the grading scale
+    before: Option<String>, // JSON snapshot; None when the entity was created
+    after: Option<String>,  // JSON snapshot; None when the entity was deleted
+}
+
+impl Storable for AuditEntry {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(migration::encode(self))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        migration::decode(&bytes)
+    }
+}
+
+/// Represents the criteria an audit entry must match; unset criteria match every entry.
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+struct AuditFilter {
+    caller: Option<Principal>,
+    endpoint: Option<String>,
+    entity: Option<String>,
+    entity_id: Option<u64>,
+    from: Option<u64>, // Inclusive lower bound of the timestamp
+    to: Option<u64>,   // Inclusive upper bound of the timestamp
+}
+
+impl AuditFilter {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.caller.map_or(true, |caller| entry.caller == caller)
+            && self.endpoint.as_ref().map_or(true, |endpoint| entry.endpoint == *endpoint)
+            && self.entity.as_ref().map_or(true, |entity| entry.entity == *entity)
+            && self.entity_id.map_or(true, |entity_id| entry.entity_id == Some(entity_id))
+            && self.from.map_or(true, |from| entry.timestamp >= from)
+            && self.to.map_or(true, |to| entry.timestamp <= to)
+    }
+}
+
+/// Maximum number of audit entries examined by a single audit log query.
+const MAX_AUDIT_SCAN: u64 = 10_000;
+
+/// Represents the first and last day of an academic year.
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, Default)]
+struct AcademicYear {
+    start: Date,
+    end: Date,
+}
+
+/// Represents the school-wide settings.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct SchoolSettings {
+    school_name: String,
+    timezone: String, // IANA time zone name, e.g. "Europe/Berlin"
+    academic_year: AcademicYear,
+}
+
+impl Storable for SchoolSettings {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(migration::encode(self))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        migration::decode(&bytes)
+    }
+}
+
+/// Represents the arguments of the canister on install and, optionally, on upgrade.
+#[derive(candid::CandidType, Serialize, Deserialize)]
+struct InitArgs {
+    admins: Vec<Principal>, // Granted the admin role, in addition to the existing admins
+    school_name: String,
+    timezone: String,
+    academic_year: AcademicYear,
+    grading_scale: Option<GradingScale>, // None keeps the current grading scale
+}
+
+/// Represents the school-wide configuration.
+#[derive(candid::CandidType, Serialize, Deserialize)]
+struct SchoolConfig {
+    school_name: String,
+    timezone: String,
+    academic_year: AcademicYear,
+    grading_scale: GradingScale,
+}
+
+/// Maximum length of a time zone name.
+const MAX_TIMEZONE_LENGTH: usize = 64;
+
+/// Maximum number of courses assigned to a teacher, keeping a teacher within its `MAX_SIZE`.
+const MAX_COURSES_PER_TEACHER: usize = 20;
+
+/// Maximum number of schedule slots taught by a teacher, keeping a teacher within its `MAX_SIZE`.
+const MAX_SLOTS_PER_TEACHER: usize = 40;
+
+/// Start of the school day, the earliest time a classroom is reported as available.
+const SCHOOL_DAY_START: TimeOfDay = TimeOfDay { hour: 7, minute: 0 };
+
+/// End of the school day, the latest time a classroom is reported as available.
+const SCHOOL_DAY_END: TimeOfDay = TimeOfDay { hour: 19, minute: 0 };
+
+/// Maximum number of days covered by a single availability query.
+const MAX_AVAILABILITY_DAYS: i64 = 31;
+
+/// Maximum length of an assessment name, keeping a record within its `MAX_SIZE`.
+const MAX_ASSESSMENT_NAME_LENGTH: usize = 256;
+
+/// Maximum length of an attendance note, keeping a record within its `MAX_SIZE`.
+const MAX_ATTENDANCE_NOTE_LENGTH: usize = 512;
+
+thread_local! {
+    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
+        MemoryManager::init(DefaultMemoryImpl::default())
+    );
+
+    // Shared by attendance, performance and schedule slot records. Before the entity
+    // counters below existed it also numbered students, teachers, courses and classrooms.
+    static ID_COUNTER: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(0))), 0)
+            .expect("Cannot create a counter")
+    );
+
+    static STUDENT_STORAGE: RefCell<StableBTreeMap<StudentId, Student, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1)))
+    ));
+
+    static TEACHER_STORAGE: RefCell<StableBTreeMap<TeacherId, Teacher, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2)))
+    ));
+
+    static COURSE_STORAGE: RefCell<StableBTreeMap<CourseId, Course, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3)))
+    ));
+
+    static CLASSROOM_STORAGE: RefCell<StableBTreeMap<ClassroomId, Classroom, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(4)))
+    ));
+
+    static ATTENDANCE_STORAGE: RefCell<StableBTreeMap<u64, AttendanceRecord, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(5)))
+    ));
+
+    // (student_id, attendance_id) pairs, backing per-student attendance lookups.
+    static STUDENT_ATTENDANCE_INDEX: RefCell<IdIndex<StudentId>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(6)))
+    ));
+
+    // (course_id, attendance_id) pairs, backing per-course attendance lookups.
+    static COURSE_ATTENDANCE_INDEX: RefCell<IdIndex<CourseId>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(7)))
+    ));
+
+    static PERFORMANCE_STORAGE: RefCell<StableBTreeMap<u64, PerformanceRecord, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(8)))
+    ));
+
+    // (student_id, performance_id) pairs, backing per-student gradebooks.
+    static STUDENT_PERFORMANCE_INDEX: RefCell<IdIndex<StudentId>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(9)))
+    ));
+
+    // (course_id, performance_id) pairs, backing per-course gradebooks.
+    static COURSE_PERFORMANCE_INDEX: RefCell<IdIndex<CourseId>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(10)))
+    ));
+
+    static GRADING_SCALE: RefCell<Cell<GradingScale, Memory>> = RefCell::new(
+        Cell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(11))), GradingScale::default())
+            .expect("Cannot create the grading scale")
+    );
+
+    static SCHEDULE_SLOT_STORAGE: RefCell<StableBTreeMap<u64, ScheduleSlot, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(12)))
+    ));
+
+    // (course_id, slot_id) pairs, backing course timetables.
+    static COURSE_SLOT_INDEX: RefCell<IdIndex<CourseId>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(13)))
+    ));
+
+    // (classroom_id, slot_id) pairs, the bookings of each classroom.
+    static CLASSROOM_SLOT_INDEX: RefCell<IdIndex<ClassroomId>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(14)))
+    ));
+
+    static STUDENT_EMAIL_INDEX: RefCell<StringIndex<StudentId>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(15)))
+    ));
+
+    static STUDENT_NAME_INDEX: RefCell<StringIndex<StudentId>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(16)))
+    ));
+
+    static TEACHER_EMAIL_INDEX: RefCell<StringIndex<TeacherId>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(17)))
+    ));
+
+    static TEACHER_NAME_INDEX: RefCell<StringIndex<TeacherId>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(18)))
+    ));
+
+    static STUDENT_ID_COUNTER: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(19))), 0)
+            .expect("Cannot create the student ID counter")
+    );
+
+    static TEACHER_ID_COUNTER: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(20))), 0)
+            .expect("Cannot create the teacher ID counter")
+    );
+
+    static COURSE_ID_COUNTER: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(21))), 0)
+            .expect("Cannot create the course ID counter")
+    );
+
+    static CLASSROOM_ID_COUNTER: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(22))), 0)
+            .expect("Cannot create the classroom ID counter")
+    );
+
+    static ROLE_STORAGE: RefCell<StableBTreeMap<PrincipalKey, RoleSet, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(23)))
+    ));
+
+    static SCHOOL_SETTINGS: RefCell<Cell<SchoolSettings, Memory>> = RefCell::new(
+        Cell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(24))), SchoolSettings::default())
+            .expect("Cannot create the school settings")
+    );
+
+    static GUARDIAN_ID_COUNTER: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(25))), 0)
+            .expect("Cannot create the guardian ID counter")
+    );
+
+    static GUARDIAN_STORAGE: RefCell<StableBTreeMap<GuardianId, Guardian, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(26)))
+    ));
+
+    // (student_id, guardian_id) pairs, the guardians of each student.
+    static STUDENT_GUARDIAN_INDEX: RefCell<IdIndex<StudentId>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(27)))
+    ));
+
+    // (guardian_id, student_id) pairs, the students of each guardian.
+    static GUARDIAN_STUDENT_INDEX: RefCell<IdIndex<GuardianId>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(28)))
+    ));
+
+    static AUDIT_LOG: RefCell<Log<AuditEntry, Memory, Memory>> = RefCell::new(
+        Log::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(29))),
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(30))),
+        )
+        .expect("Cannot create the audit log")
+    );
+
+    static STUDENT_ARCHIVE: RefCell<Archive<StudentId, Student>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(31)))
+    ));
+
+    static TEACHER_ARCHIVE: RefCell<Archive<TeacherId, Teacher>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(32)))
+    ));
+
+    static COURSE_ARCHIVE: RefCell<Archive<CourseId, Course>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(33)))
+    ));
+
+    static CLASSROOM_ARCHIVE: RefCell<Archive<ClassroomId, Classroom>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(34)))
+    ));
+
+    static GUARDIAN_ARCHIVE: RefCell<Archive<GuardianId, Guardian>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(35)))
+    ));
+
+    // The `migration::STORAGE_LAYOUT_VERSION` the stored records were last rewritten in.
+    static STORAGE_LAYOUT: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(36))), 0)
+            .expect("Cannot create the storage layout version")
+    );
+}
+
+/// Appends a mutation by the caller to the audit log.
+///
+/// Traps if the log cannot grow, so that no mutation goes unrecorded.
+fn audit<T: serde::Serialize>(
+    endpoint: &str,
+    entity: &str,
+    entity_id: Option<u64>,
+    before: Option<&T>,
+    after: Option<&T>,
+) {
+    let snapshot = |value: &T| serde_json::to_string(value).expect("Cannot serialize an audit snapshot");
+    AUDIT_LOG.with(|log| {
+        let log = log.borrow();
+        let entry = AuditEntry {
+            id: log.len(),
+            caller: caller(),
+            timestamp: time(),
+            endpoint: endpoint.to_string(),
+            entity: entity.to_string(),
+            entity_id,
+            before: before.map(snapshot),
+            after: after.map(snapshot),
+        };
+        if log.append(&entry).is_err() {
+            ic_cdk::trap("Cannot append to the audit log");
+        }
+    });
+}
+
+/// Increments the counter and returns its new value.
+fn next_id(counter: &'static LocalKey<RefCell<IdCell>>) -> u64 {
+    counter.with(|counter| {
+        let current_value = *counter.borrow().get();
+        let _ = counter.borrow_mut().set(current_value + 1);
+        current_value + 1
+    })
+}
+
+/// Replaces a stored record by its archived form, recording the reason, the caller and the
+/// time, and returns the archived record.
+fn archive_record<K: EntityId, T: Archivable>(
+    endpoint: &str,
+    storage: &'static LocalKey<RefCell<StableBTreeMap<K, T, Memory>>>,
+    archive: &'static LocalKey<RefCell<Archive<K, T>>>,
+    id: K,
+    record: T,
+    reason: &str,
+) -> Archived<T> {
+    let existing = storage.with(|storage| storage.borrow_mut().remove(&id));
+    let archived = Archived {
+        record,
+        archive: ArchiveInfo {
+            reason: reason.to_string(),
+            archived_by: caller(),
+            archived_at: time(),
+        },
+    };
+    archive.with(|archive| archive.borrow_mut().insert(id, archived.clone()));
+    audit(
+        endpoint,
+        T::ENTITY,
+        Some(id.into()),
+        existing.map(|existing| serde_json::json!(existing)).as_ref(),
+        Some(&serde_json::json!(archived)),
+    );
+
+    archived
+}
+
+fn validate_archive_reason(reason: &str) -> Result<(), Error> {
+    Validator::new().required("reason", reason, MAX_TEXT_LENGTH).finish()
+}
+
+/// Ensures a delete under the policy leaves no record referring to the deleted one.
+fn ensure_deletable(
+    policy: DeletePolicy,
+    entity: &str,
+    id: impl Into<u64>,
+    references: &[RecordRef],
+) -> Result<(), Error> {
+    if policy != DeletePolicy::Restrict || references.is_empty() {
+        return Ok(());
+    }
+    let list: Vec<String> = references
+        .iter()
+        .map(|reference| format!("{} {}", reference.entity, reference.id))
+        .collect();
+    Err(Error::conflict(
+        entity,
+        format!(
+            "{} {} is referenced by {}; delete it with the Nullify or Cascade policy instead",
+            entity,
+            id.into(),
+            list.join(", ")
+        ),
+    ))
+}
+
+/// Moves an archived record back into its storage once `check` accepts it, and returns the
+/// restored record.
+fn restore_record<K: EntityId, T: Archivable>(
+    endpoint: &str,
+    storage: &'static LocalKey<RefCell<StableBTreeMap<K, T, Memory>>>,
+    archive: &'static LocalKey<RefCell<Archive<K, T>>>,
+    id: K,
+    check: impl FnOnce(&T) -> Result<(), Error>,
+) -> Result<T, Error> {
+    let archived = archive
+        .with(|archive| archive.borrow().get(&id))
+        .ok_or_else(|| Error::not_found(T::ENTITY, id))?;
+    check(&archived.record)?;
+
+    let mut record = archived.record.clone();
+    record.metadata_mut().touch();
+    archive.with(|archive| archive.borrow_mut().remove(&id));
+    storage.with(|storage| storage.borrow_mut().insert(id, record.clone()));
+    audit(
+        endpoint,
+        T::ENTITY,
+        Some(id.into()),
+        Some(&serde_json::json!(archived)),
+        Some(&serde_json::json!(record)),
+    );
+
+    Ok(record)
+}
+
+/// Removes an archived record for good and returns it.
+fn purge_record<K: EntityId, T: Archivable>(
+    endpoint: &str,
+    archive: &'static LocalKey<RefCell<Archive<K, T>>>,
+    id: K,
+) -> Result<Archived<T>, Error> {
+    let archived = archive
+        .with(|archive| archive.borrow_mut().remove(&id))
+        .ok_or_else(|| Error::not_found(T::ENTITY, id))?;
+    audit(endpoint, T::ENTITY, Some(id.into()), Some(&archived), None);
+
+    Ok(archived)
+}
+
+fn insert_index_keys<K: EntityId>(
+    index: &'static LocalKey<RefCell<StringIndex<K>>>,
+    keys: Vec<IndexKey>,
+    id: K,
+) {
+    index.with(|index| {
+        let mut index = index.borrow_mut();
+        for key in keys {
+            index.insert((key, id), ());
+        }
+    });
+}
+
+fn remove_index_keys<K: EntityId>(
+    index: &'static LocalKey<RefCell<StringIndex<K>>>,
+    keys: Vec<IndexKey>,
+    id: K,
+) {
+    index.with(|index| {
+        let mut index = index.borrow_mut();
+        for key in keys {
+            index.remove(&(key, id));
+        }
+    });
+}
+
+/// Returns the IDs stored under keys starting with the prefix, in key order without duplicates.
+fn index_ids_with_prefix<K: EntityId>(
+    index: &'static LocalKey<RefCell<StringIndex<K>>>,
+    prefix: &IndexKey,
+    limit: usize,
+) -> Vec<K> {
+    index.with(|index| {
+        let mut ids: Vec<K> = Vec::new();
+        for ((key, id), _) in index.borrow().range((prefix.clone(), K::default())..) {
+            if !key.0.starts_with(&prefix.0) || ids.len() >= limit {
+                break;
+            }
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+        ids
+    })
+}
+
+/// Returns the IDs stored under exactly the key.
+fn index_ids_with_key<K: EntityId>(
+    index: &'static LocalKey<RefCell<StringIndex<K>>>,
+    key: &IndexKey,
+) -> Vec<K> {
+    index.with(|index| {
+        index
+            .borrow()
+            .range((key.clone(), K::default())..=(key.clone(), K::from(u64::MAX)))
+            .map(|((_, id), _)| id)
+            .collect()
+    })
+}
+
+/// Ensures no student or teacher other than `owner` is registered with the email address.
+fn ensure_email_available(email: &str, owner: Option<(&str, u64)>) -> Result<(), Error> {
+    let key = IndexKey::email(email);
+    let holders: [(&str, Vec<u64>); 2] = [
+        (
+            "Student",
+            index_ids_with_key(&STUDENT_EMAIL_INDEX, &key).into_iter().map(u64::from).collect(),
+        ),
+        (
+            "Teacher",
+            index_ids_with_key(&TEACHER_EMAIL_INDEX, &key).into_iter().map(u64::from).collect(),
+        ),
+    ];
+    for (entity, ids) in holders {
+        if let Some(id) = ids.into_iter().find(|id| owner != Some((entity, *id))) {
+            return Err(Error::EmailInUse {
+                email: key.0,
+                entity: entity.to_string(),
+                id,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn index_student(student: &Student) {
+    insert_index_keys(&STUDENT_EMAIL_INDEX, vec![IndexKey::email(&student.email)], student.id);
+    insert_index_keys(&STUDENT_NAME_INDEX, IndexKey::name(&student.name), student.id);
+}
+
+fn unindex_student(student: &Student) {
+    remove_index_keys(&STUDENT_EMAIL_INDEX, vec![IndexKey::email(&student.email)], student.id);
+    remove_index_keys(&STUDENT_NAME_INDEX, IndexKey::name(&student.name), student.id);
+}
+
+fn index_teacher(teacher: &Teacher) {
+    insert_index_keys(&TEACHER_EMAIL_INDEX, vec![IndexKey::email(&teacher.email)], teacher.id);
+    insert_index_keys(&TEACHER_NAME_INDEX, IndexKey::name(&teacher.name), teacher.id);
+}
+
+fn unindex_teacher(teacher: &Teacher) {
+    remove_index_keys(&TEACHER_EMAIL_INDEX, vec![IndexKey::email(&teacher.email)], teacher.id);
+    remove_index_keys(&TEACHER_NAME_INDEX, IndexKey::name(&teacher.name), teacher.id);
+}
+
+/// Returns the roles granted to the principal.
+fn roles_of(principal: Principal) -> Vec<Role> {
+    ROLE_STORAGE
+        .with(|storage| storage.borrow().get(&PrincipalKey(principal)))
+        .map(|set| set.roles)
+        .unwrap_or_default()
+}
+
+/// Ensures the caller is an admin or holds a role accepted by `allowed`, and returns the caller.
+///
+/// Anonymous callers are always rejected. Controllers of the canister act as admins, so that
+/// the first admin can be granted after installation.
+fn authorize(allowed: impl Fn(&Role) -> bool) -> Result<Principal, Error> {
+    let caller = caller();
+    if caller == Principal::anonymous() {
+        return Err(Error::unauthorized("Anonymous callers are not allowed"));
+    }
+    if is_controller(&caller) {
+        return Ok(caller);
+    }
+    if roles_of(caller).iter().any(|role| *role == Role::Admin || allowed(role)) {
+        Ok(caller)
+    } else {
+        Err(Error::unauthorized("The caller does not have a role that allows this call"))
+    }
+}
+
+/// Ensures the caller is an admin.
+fn require_admin() -> Result<Principal, Error> {
+    authorize(|_| false)
+}
+
+/// Ensures the caller is an admin or a registrar.
+fn require_registrar() -> Result<Principal, Error> {
+    authorize(|role| *role == Role::Registrar)
+}
+
+/// Ensures the caller is an admin, a registrar or a teacher.
+fn require_staff() -> Result<Principal, Error> {
+    authorize(|role| matches!(role, Role::Registrar | Role::Teacher { .. }))
+}
+
+/// Ensures the caller holds any role.
+fn require_member() -> Result<Principal, Error> {
+    authorize(|_| true)
+}
+
+/// Ensures the caller is the teacher, or an admin.
+fn require_teacher(teacher_id: TeacherId) -> Result<Principal, Error> {
+    authorize(|role| *role == Role::Teacher { teacher_id })
+}
+
+/// Ensures the caller is the teacher, a registrar or an admin.
+fn require_teacher_access(teacher_id: TeacherId) -> Result<Principal, Error> {
+    authorize(|role| *role == Role::Registrar || *role == Role::Teacher { teacher_id })
+}
+
+/// Ensures the caller is the student, staff or an admin.
+fn require_student_access(student_id: StudentId) -> Result<Principal, Error> {
+    authorize(|role| {
+        matches!(role, Role::Registrar | Role::Teacher { .. }) || *role == Role::Student { student_id }
+    })
+}
+
+/// Ensures the caller is the guardian, staff or an admin.
+fn require_guardian_access(guardian_id: GuardianId) -> Result<Principal, Error> {
+    authorize(|role| {
+        matches!(role, Role::Registrar | Role::Teacher { .. }) || *role == Role::Guardian { guardian_id }
+    })
+}
+
+/// Ensures the caller is the teacher of the course, a registrar or an admin.
+fn require_course_access(course_id: CourseId) -> Result<Principal, Error> {
+    let teacher_id = COURSE_STORAGE
+        .with(|storage| storage.borrow().get(&course_id))
+        .and_then(|course| course.teacher_id);
+    authorize(|role| match role {
+        Role::Registrar => true,
+        Role::Teacher { teacher_id: id } => Some(*id) == teacher_id,
+        _ => false,
+    })
+}
+
+/// Starts the entity ID counters after the last ID handed out by the shared counter, so
+/// the IDs of existing records stay unique within their kind.
+fn migrate_id_counters() {
+    let shared_value = ID_COUNTER.with(|counter| *counter.borrow().get());
+    for counter in [&STUDENT_ID_COUNTER, &TEACHER_ID_COUNTER, &COURSE_ID_COUNTER, &CLASSROOM_ID_COUNTER] {
+        counter.with(|counter| {
+            if *counter.borrow().get() == 0 {
+                let _ = counter.borrow_mut().set(shared_value);
+            }
+        });
+    }
+}
+
+fn validate_school_settings(settings: &SchoolSettings) -> Result<(), Error> {
+    let AcademicYear { start, end } = settings.academic_year;
+    Validator::new()
+        .required("school_name", &settings.school_name, MAX_NAME_LENGTH)
+        .required("timezone", &settings.timezone, MAX_TIMEZONE_LENGTH)
+        .check("academic_year.start", start.is_valid(), "Must be a valid calendar date")
+        .check("academic_year.end", end.is_valid(), "Must be a valid calendar date")
+        .check("academic_year.end", end > start, "Must be after the start of the academic year")
+        .finish()
+}
+
+/// Stores the school settings and grading scale and grants the admin role to the listed admins.
+fn apply_init_args(endpoint: &str, args: InitArgs) -> Result<(), Error> {
+    let settings = SchoolSettings {
+        school_name: args.school_name,
+        timezone: args.timezone,
+        academic_year: args.academic_year,
+    };
+    validate_school_settings(&settings)?;
+    if let Some(scale) = args.grading_scale {
+        store_grading_scale(scale)?;
+    }
+    for admin in args.admins {
+        add_role(endpoint, admin, Role::Admin)?;
+    }
+    let existing_settings = SCHOOL_SETTINGS.with(|cell| {
+        cell.borrow_mut()
+            .set(settings.clone())
+            .map_err(|_| Error::invalid_input("school_name", "School settings are too large"))
+    })?;
+    audit(endpoint, "SchoolSettings", None, Some(&existing_settings), Some(&settings));
+    Ok(())
+}
+
+/// Sets up the school from the install arguments.
+#[ic_cdk::init]
+fn init(args: InitArgs) {
+    STORAGE_LAYOUT
+        .with(|layout| layout.borrow_mut().set(migration::STORAGE_LAYOUT_VERSION))
+        .expect("Cannot set the storage layout version");
+    if let Err(error) = apply_init_args("init", args) {
+        ic_cdk::trap(&format!("Invalid init arguments: {:?}", error));
+    }
+}
+
+/// Creates a guardian from the free-text guardian details of a student. The first line names
+/// the guardian, the first phone number and email address found become its contact details and
+/// the text is kept as its notes, each cut to the limits of its field.
+fn legacy_guardian(id: GuardianId, details: &str, metadata: Metadata) -> Guardian {
+    let email = validation::find_email(details).unwrap_or_default();
+    let phone = if email.is_empty() {
+        validation::find_phone(details).map(str::to_string)
+    } else {
+        validation::find_phone(&details.replace(email, " ")).map(str::to_string)
+    };
+    Guardian {
+        id,
+        name: validation::truncate(details.lines().next().unwrap_or_default(), MAX_NAME_LENGTH).to_string(),
+        phone: phone.unwrap_or_default(),
+        email: email.to_string(),
+        notes: validation::truncate(details, MAX_LONG_TEXT_LENGTH).to_string(),
+        metadata,
+        ..Default::default()
+    }
+}
+
+/// Reads and writes back every record of a map, storing it in its current version.
+fn rewrite_records<K: BoundedStorable + Ord + Clone, V: BoundedStorable>(
+    storage: &'static LocalKey<RefCell<StableBTreeMap<K, V, Memory>>>,
+) {
+    storage.with(|storage| {
+        let mut storage = storage.borrow_mut();
+        let records: Vec<(K, V)> = storage.iter().collect();
+        for (id, record) in records {
+            storage.insert(id, record);
+        }
+    });
+}
+
+/// Reads and writes back the value of a cell, storing it in its current version.
+fn rewrite_cell<T: Storable + Clone>(cell: &'static LocalKey<RefCell<Cell<T, Memory>>>) {
+    cell.with(|cell| {
+        let value = cell.borrow().get().clone();
+        cell.borrow_mut().set(value).expect("Cannot rewrite a stored cell");
+    });
+}
+
+/// Rewrites the stored records in their current versions once per storage layout, so that
+/// records of older layouts do not linger. Reads migrate older records in any case.
+fn migrate_storage_layouts() {
+    if STORAGE_LAYOUT.with(|layout| *layout.borrow().get()) >= migration::STORAGE_LAYOUT_VERSION {
+        return;
+    }
+    rewrite_records(&STUDENT_STORAGE);
+    rewrite_records(&TEACHER_STORAGE);
+    rewrite_records(&COURSE_STORAGE);
+    rewrite_records(&CLASSROOM_STORAGE);
+    rewrite_records(&GUARDIAN_STORAGE);
+    rewrite_records(&STUDENT_ARCHIVE);
+    rewrite_records(&TEACHER_ARCHIVE);
+    rewrite_records(&COURSE_ARCHIVE);
+    rewrite_records(&CLASSROOM_ARCHIVE);
+    rewrite_records(&GUARDIAN_ARCHIVE);
+    rewrite_records(&ATTENDANCE_STORAGE);
+    rewrite_records(&PERFORMANCE_STORAGE);
+    rewrite_records(&SCHEDULE_SLOT_STORAGE);
+    rewrite_records(&ROLE_STORAGE);
+    rewrite_cell(&GRADING_SCALE);
+    rewrite_cell(&SCHOOL_SETTINGS);
+    // The audit log is append-only: its entries stay as written, and reads decode both forms.
+    STORAGE_LAYOUT
+        .with(|layout| layout.borrow_mut().set(migration::STORAGE_LAYOUT_VERSION))
+        .expect("Cannot set the storage layout version");
+}
+
+/// Turns the free-text guardian details of the students stored before guardians were
+/// separate records into guardians linked to those students.
+fn migrate_guardian_details() {
+    if GUARDIAN_ID_COUNTER.with(|counter| *counter.borrow().get()) != 0
+        || STUDENT_STORAGE.with(|storage| storage.borrow().is_empty())
+    {
+        return;
+    }
+    // A read-only view of the student map that decodes only the legacy field.
+    let legacy_students: StableBTreeMap<StudentId, LegacyGuardianDetails, Memory> =
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1))));
+    let details: Vec<(StudentId, String)> = legacy_students
+        .iter()
+        .filter_map(|(id, legacy)| Some((id, legacy.guardian_details?.trim().to_string())))
+        .filter(|(_, details)| !details.is_empty())
+        .collect();
+
+    for (student_id, details) in details {
+        let id = GuardianId(next_id(&GUARDIAN_ID_COUNTER));
+        let guardian = legacy_guardian(id, &details, Metadata::new());
+        GUARDIAN_STORAGE.with(|storage| storage.borrow_mut().insert(id, guardian.clone()));
+        insert_guardian_link(student_id, id);
+        audit("post_upgrade", "Guardian", Some(id.into()), None, Some(&guardian));
+    }
+}
+
+/// Drops the guardian roles granted before guardian roles named their guardian, removing the
+/// principals left without any role.
+fn migrate_guardian_roles() {
+    // A read-only view of the role map that decodes the legacy layout.
+    let legacy_roles: StableBTreeMap<PrincipalKey, migration::RoleSetV1, Memory> =
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(23))));
+    let legacy_sets: Vec<(PrincipalKey, migration::RoleSetV1)> =
+        legacy_roles.iter().filter(|(_, legacy)| legacy.has_guardian_role()).collect();
+
+    for (key, legacy) in legacy_sets {
+        let before = role_snapshot(key.0, &legacy.roles);
+        let set = RoleSet::from(legacy);
+        ROLE_STORAGE.with(|storage| {
+            let mut storage = storage.borrow_mut();
+            if set.roles.is_empty() {
+                storage.remove(&key);
+            } else {
+                storage.insert(key, set.clone());
+            }
+        });
+        audit("post_upgrade", "Role", None, Some(&before), Some(&role_snapshot(key.0, &set.roles)));
+    }
+}
+
+/// Returns the IDs and texts of the free-text dates stored in a map that are not valid dates.
+fn invalid_legacy_dates<K: BoundedStorable + Ord + Clone, T: BoundedStorable>(
+    memory_id: MemoryId,
+) -> Vec<(K, String)> {
+    // A read-only view of the map that decodes only the legacy date.
+    let legacy_records: StableBTreeMap<K, LegacyTextDate<T>, Memory> =
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(memory_id)));
+    legacy_records
+        .iter()
+        .filter_map(|(id, legacy)| Some((id, legacy.text()?)))
+        .filter(|(_, text)| Date::parse(text).is_none())
+        .collect()
+}
+
+/// Records in the audit log the free-text dates that are not valid dates, before the layout
+/// migration replaces them by the default date, so that they can be corrected by hand.
+fn audit_invalid_legacy_dates() {
+    if STORAGE_LAYOUT.with(|layout| *layout.borrow().get()) >= migration::STORAGE_LAYOUT_VERSION {
+        return;
+    }
+    let audit_date = |entity: &str, id: u64, field: &str, text: String| {
+        let before = serde_json::json!({ "id": id, field: text });
+        let after = serde_json::json!({ "id": id, field: Date::default() });
+        audit("post_upgrade", entity, Some(id), Some(&before), Some(&after));
+    };
+    for (id, text) in invalid_legacy_dates::<StudentId, Student>(MemoryId::new(1)) {
+        audit_date("Student", id.into(), "date_of_birth", text);
+    }
+    for (id, text) in invalid_legacy_dates::<TeacherId, Teacher>(MemoryId::new(2)) {
+        audit_date("Teacher", id.into(), "employment_date", text);
+    }
+}
+
+/// Migrates the ID counters, guardian details and guardian roles, indexes the students and
+/// teachers stored before the secondary indexes existed and applies the upgrade arguments, if any.
+#[ic_cdk::post_upgrade]
+fn post_upgrade(args: Option<InitArgs>) {
+    migrate_id_counters();
+    // Runs before the layout migration, which drops the legacy details from the students.
+    migrate_guardian_details();
+    migrate_guardian_roles();
+    audit_invalid_legacy_dates();
+    migrate_storage_layouts();
+    if STUDENT_EMAIL_INDEX.with(|index| index.borrow().is_empty()) {
+        STUDENT_STORAGE.with(|storage| storage.borrow().iter().for_each(|(_, student)| index_student(&student)));
+    }
+    if TEACHER_EMAIL_INDEX.with(|index| index.borrow().is_empty()) {
+        TEACHER_STORAGE.with(|storage| storage.borrow().iter().for_each(|(_, teacher)| index_teacher(&teacher)));
+    }
+    if let Some(Err(error)) = args.map(|args| apply_init_args("post_upgrade", args)) {
+        ic_cdk::trap(&format!("Invalid upgrade arguments: {:?}", error));
+    }
+}
+
+/// Retrieves the school settings and grading scale.
+#[ic_cdk::query]
+fn get_school_config() -> Result<SchoolConfig, Error> {
+    require_member()?;
+    let settings = SCHOOL_SETTINGS.with(|cell| cell.borrow().get().clone());
+    Ok(SchoolConfig {
+        school_name: settings.school_name,
+        timezone: settings.timezone,
+        academic_year: settings.academic_year,
+        grading_scale: GRADING_SCALE.with(|scale| scale.borrow().get().clone()),
+    })
+}
+
+fn validate_student_payload(payload: &StudentPayload) -> Result<(), Error> {
+    let today = Date::today();
+    let mut validator = Validator::new();
+    validator
+        .required("name", &payload.name, MAX_NAME_LENGTH)
+        .email("email", &payload.email)
+        .range("grade_level", payload.grade_level, 0, MAX_GRADE_LEVEL)
+        .past_date("date_of_birth", payload.date_of_birth, today)
+        .max_length("address", &payload.address, MAX_TEXT_LENGTH);
+
+    if let Some(age) = payload.date_of_birth.years_until(today).filter(|_| payload.date_of_birth.is_valid()) {
+        let grade_level = u32::from(payload.grade_level);
+        validator.check(
+            "date_of_birth",
+            age >= grade_level + MIN_AGE_ABOVE_GRADE && age <= grade_level + MAX_AGE_ABOVE_GRADE,
+            &format!("An age of {} is not plausible for grade level {}", age, payload.grade_level),
+        );
+    }
+    validator.finish()
+}
+
+/// Adds a new student with the provided payload.
+#[ic_cdk::update]
+fn add_student(payload: StudentPayload) -> Result<Student, Error> {
+    require_registrar()?;
+    validate_student_payload(&payload)?;
+    ensure_email_available(&payload.email, None)?;
+
+    let id = StudentId(next_id(&STUDENT_ID_COUNTER));
+
+    let student = Student {
+        id,
+        name: payload.name,
+        grade_level: payload.grade_level,
+        enrolled_courses: Vec::new(),
+        email: payload.email,
+        date_of_birth: payload.date_of_birth,
+        address: payload.address,
+        metadata: Metadata::new(),
+    };
+
+    STUDENT_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(id, student.clone());
+    });
+    index_student(&student);
+    audit("add_student", "Student", Some(id.into()), None, Some(&student));
+
+    Ok(student)
+}
+
+/// Retrieves information about a student based on the provided ID.
+#[ic_cdk::query]
+fn get_student(id: StudentId) -> Result<Student, Error> {
+    require_student_access(id)?;
+    STUDENT_STORAGE.with(|storage| {
+        match storage.borrow().get(&id) {
+            Some(student) => Ok(student.clone()),
+            None => Err(Error::not_found("Student", id)),
+        }
+    })
+}
+
+/// Updates the provided fields of a student, leaving the others unchanged.
+#[ic_cdk::update]
+fn update_student(id: StudentId, patch: StudentPatch) -> Result<Student, Error> {
+    require_registrar()?;
+    STUDENT_STORAGE.with(|storage| {
+        let mut storage = storage.borrow_mut();
+        if let Some(existing_student) = storage.get(&id) {
+            // Patch the current values so they go through the same validation as a new student
+            let mut payload = StudentPayload::from(&existing_student);
+            patch.apply_to(&mut payload);
+            validate_student_payload(&payload)?;
+            ensure_email_available(&payload.email, Some(("Student", id.into())))?;
+
+            // Clone the existing student to make a mutable copy
+            let mut updated_student = existing_student.clone();
+
+            // Update the fields
+            updated_student.name = payload.name;
+            updated_student.grade_level = payload.grade_level;
+            updated_student.email = payload.email;
+            updated_student.date_of_birth = payload.date_of_birth;
+            updated_student.address = payload.address;
+
+            // Re-insert the updated student back into the storage
+            updated_student.metadata.touch();
+            storage.insert(id, updated_student.clone());
+            unindex_student(&existing_student);
+            index_student(&updated_student);
+            audit(
+                "update_student",
+                "Student",
+                Some(id.into()),
+                Some(&existing_student),
+                Some(&updated_student),
+            );
+
+            Ok(updated_student)
+        } else {
+            Err(Error::not_found("Student", id))
+        }
+    })
+}
+
+/// Deletes a student by archiving it with the reason, treating its enrollments according to
+/// the policy (`Restrict` by default). Archived students keep their guardian links, so that a
+/// restore brings them back to their guardians.
+#[ic_cdk::update]
+fn delete_student(
+    id: StudentId,
+    reason: String,
+    policy: Option<DeletePolicy>,
+) -> Result<DeleteSummary, Error> {
+    require_registrar()?;
+    validate_archive_reason(&reason)?;
+    let mut student = STUDENT_STORAGE
+        .with(|storage| storage.borrow().get(&id))
+        .ok_or_else(|| Error::not_found("Student", id))?;
+    let references: Vec<RecordRef> =
+        student.enrolled_courses.iter().map(|course_id| RecordRef::new("Course", *course_id)).collect();
+    ensure_deletable(policy.unwrap_or_default(), "Student", id, &references)?;
+
+    let mut summary = DeleteSummary {
+        archived: vec![RecordRef::new("Student", id)],
+        ..Default::default()
+    };
+    for course_id in std::mem::take(&mut student.enrolled_courses) {
+        if let Some(mut course) = COURSE_STORAGE.with(|storage| storage.borrow().get(&course_id)) {
+            let existing_course = course.clone();
+            course.student_ids.retain(|student_id| *student_id != id);
+            course.metadata.touch();
+            COURSE_STORAGE.with(|storage| storage.borrow_mut().insert(course_id, course.clone()));
+            audit("delete_student", "Course", Some(course_id.into()), Some(&existing_course), Some(&course));
+            summary.updated("Course", course_id);
+        }
+    }
+    unindex_student(&student);
+    archive_record("delete_student", &STUDENT_STORAGE, &STUDENT_ARCHIVE, id, student, &reason);
+
+    Ok(summary)
+}
+
+/// Restores an archived student, provided its email address has not been taken since.
+#[ic_cdk::update]
+fn restore_student(id: StudentId) -> Result<Student, Error> {
+    require_registrar()?;
+    let student = restore_record("restore_student", &STUDENT_STORAGE, &STUDENT_ARCHIVE, id, |student| {
+        ensure_email_available(&student.email, None)
+    })?;
+    index_student(&student);
+    Ok(student)
+}
+
+/// Permanently removes an archived student and its guardian links.
+#[ic_cdk::update]
+fn purge_student(id: StudentId) -> Result<(), Error> {
+    require_admin()?;
+    purge_record("purge_student", &STUDENT_ARCHIVE, id)?;
+    for guardian_id in index_ids(&STUDENT_GUARDIAN_INDEX, id) {
+        remove_guardian_link(id, GuardianId(guardian_id));
+    }
+    Ok(())
+}
+
+fn validate_teacher_payload(payload: &TeacherPayload) -> Result<(), Error> {
+    Validator::new()
+        .required("name", &payload.name, MAX_NAME_LENGTH)
+        .max_length("subject_area", &payload.subject_area, MAX_NAME_LENGTH)
+        .email("email", &payload.email)
+        .max_length("qualifications", &payload.qualifications, MAX_TEXT_LENGTH)
+        .past_date("employment_date", payload.employment_date, Date::today())
+        .max_length("address", &payload.address, MAX_TEXT_LENGTH)
+        .finish()
+}
+
+/// Adds a new teacher with the provided payload.
+#[ic_cdk::update]
+fn add_teacher(payload: TeacherPayload) -> Result<Teacher, Error> {
+    require_registrar()?;
+    validate_teacher_payload(&payload)?;
+    ensure_email_available(&payload.email, None)?;
+
+    let id = TeacherId(next_id(&TEACHER_ID_COUNTER));
+
+    let teacher = Teacher {
+        id,
+        name: payload.name,
+        subject_area: payload.subject_area,
+        assigned_courses: Vec::new(),
+        email: payload.email,
+        qualifications: payload.qualifications,
+        employment_date: payload.employment_date,
+        address: payload.address,
+        schedule: Vec::new(),
+        metadata: Metadata::new(),
+    };
+
+    TEACHER_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(id, teacher.clone());
+    });
+    index_teacher(&teacher);
+    audit("add_teacher", "Teacher", Some(id.into()), None, Some(&teacher));
+
+    Ok(teacher)
+}
+
+/// Retrieves information about a teacher based on the provided ID.
+#[ic_cdk::query]
+fn get_teacher(id: TeacherId) -> Result<Teacher, Error> {
+    require_staff()?;
+    TEACHER_STORAGE.with(|storage| {
+        match storage.borrow().get(&id) {
+            Some(teacher) => Ok(teacher.clone()),
+            None => Err(Error::not_found("Teacher", id)),
+        }
+    })
+}
+
+/// Updates the provided fields of a teacher, leaving the others unchanged.
+#[ic_cdk::update]
+fn update_teacher(id: TeacherId, patch: TeacherPatch) -> Result<Teacher, Error> {
+    require_registrar()?;
+    TEACHER_STORAGE.with(|storage| {
+        let mut storage = storage.borrow_mut();
+        if let Some(existing_teacher) = storage.get(&id) {
+            // Patch the current values so they go through the same validation as a new teacher
+            let mut payload = TeacherPayload::from(&existing_teacher);
+            patch.apply_to(&mut payload);
+            validate_teacher_payload(&payload)?;
+            ensure_email_available(&payload.email, Some(("Teacher", id.into())))?;
+
+            // Clone the existing teacher to make a mutable copy
+            let mut updated_teacher = existing_teacher.clone();
+
+            // Update the fields
+            updated_teacher.name = payload.name;
+            updated_teacher.subject_area = payload.subject_area;
+            updated_teacher.email = payload.email;
+            updated_teacher.qualifications = payload.qualifications;
+            updated_teacher.employment_date = payload.employment_date;
+            updated_teacher.address = payload.address;
+
+            // Re-insert the updated teacher back into the storage
+            updated_teacher.metadata.touch();
+            storage.insert(id, updated_teacher.clone());
+            unindex_teacher(&existing_teacher);
+            index_teacher(&updated_teacher);
+            audit(
+                "update_teacher",
+                "Teacher",
+                Some(id.into()),
+                Some(&existing_teacher),
+                Some(&updated_teacher),
+            );
+
+            Ok(updated_teacher)
+        } else {
+            Err(Error::not_found("Teacher", id))
+        }
+    })
+}
+
+/// Deletes a teacher by archiving it with the reason, treating the courses it teaches according
+/// to the policy (`Restrict` by default): `Nullify` leaves them without a teacher and `Cascade`
+/// deletes them as well.
+#[ic_cdk::update]
+fn delete_teacher(
+    id: TeacherId,
+    reason: String,
+    policy: Option<DeletePolicy>,
+) -> Result<DeleteSummary, Error> {
+    require_registrar()?;
+    validate_archive_reason(&reason)?;
+    let policy = policy.unwrap_or_default();
+    let mut teacher = TEACHER_STORAGE
+        .with(|storage| storage.borrow().get(&id))
+        .ok_or_else(|| Error::not_found("Teacher", id))?;
+    let courses: Vec<Course> = COURSE_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .iter()
+            .map(|(_, course)| course)
+            .filter(|course| course.teacher_id == Some(id))
+            .collect()
+    });
+    let references: Vec<RecordRef> =
+        courses.iter().map(|course| RecordRef::new("Course", course.id)).collect();
+    ensure_deletable(policy, "Teacher", id, &references)?;
+
+    let mut summary = DeleteSummary {
+        archived: vec![RecordRef::new("Teacher", id)],
+        ..Default::default()
+    };
+    for mut course in courses {
+        let existing_course = course.clone();
+        course.teacher_id = None;
+        if policy == DeletePolicy::Cascade {
+            archive_course("delete_teacher", course, &reason, &mut summary);
+        } else {
+            course.metadata.touch();
+            COURSE_STORAGE.with(|storage| storage.borrow_mut().insert(course.id, course.clone()));
+            audit("delete_teacher", "Course", Some(course.id.into()), Some(&existing_course), Some(&course));
+            summary.updated("Course", course.id);
+        }
+    }
+    teacher.assigned_courses.clear();
+    teacher.schedule.clear();
+    unindex_teacher(&teacher);
+    archive_record("delete_teacher", &TEACHER_STORAGE, &TEACHER_ARCHIVE, id, teacher, &reason);
+
+    Ok(summary)
+}
+
+/// Restores an archived teacher, provided its email address has not been taken since.
+#[ic_cdk::update]
+fn restore_teacher(id: TeacherId) -> Result<Teacher, Error> {
+    require_registrar()?;
+    let teacher = restore_record("restore_teacher", &TEACHER_STORAGE, &TEACHER_ARCHIVE, id, |teacher| {
+        ensure_email_available(&teacher.email, None)
+    })?;
+    index_teacher(&teacher);
+    Ok(teacher)
+}
+
+/// Permanently removes an archived teacher.
+#[ic_cdk::update]
+fn purge_teacher(id: TeacherId) -> Result<(), Error> {
+    require_admin()?;
+    purge_record("purge_teacher", &TEACHER_ARCHIVE, id).map(|_| ())
+}
+
+fn validate_course_payload(payload: &CoursePayload) -> Result<(), Error> {
+    Validator::new()
+        .required("name", &payload.name, MAX_NAME_LENGTH)
+        .required("description", &payload.description, MAX_LONG_TEXT_LENGTH)
+        .finish()
+}
+
+/// Adds a new course with the provided payload.
+#[ic_cdk::update]
+fn add_course(payload: CoursePayload) -> Result<Course, Error> {
+    require_registrar()?;
+    validate_course_payload(&payload)?;
+    let teacher = check_course_teacher(None, payload.teacher_id)?;
+
+    let id = CourseId(next_id(&COURSE_ID_COUNTER));
+
+    let mut course = Course {
+        id,
+        name: payload.name,
+        description: payload.description,
+        teacher_id: None, // Set below, once the teacher is checked
+        student_ids: Vec::new(),
+        syllabus: String::new(), // Initial empty syllabus
+        course_materials: Vec::new(), // Initial empty course materials
+        metadata: Metadata::new(),
+    };
+    set_course_teacher("add_course", &mut course, teacher);
+
+    COURSE_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(id, course.clone());
+    });
+    audit("add_course", "Course", Some(id.into()), None, Some(&course));
+
+    Ok(course)
+}
+
+/// Retrieves information about a course based on the provided ID.
+#[ic_cdk::query]
+fn get_course(id: CourseId) -> Result<Course, Error> {
+    require_member()?;
+    COURSE_STORAGE.with(|storage| {
+        match storage.borrow().get(&id) {
+            Some(course) => Ok(course.clone()),
+            None => Err(Error::not_found("Course", id)),
+        }
+    })
+}
+
+/// Updates the provided fields of a course, leaving the others unchanged.
+#[ic_cdk::update]
+fn update_course(id: CourseId, patch: CoursePatch) -> Result<Course, Error> {
+    require_registrar()?;
+    COURSE_STORAGE.with(|storage| {
+        let mut storage = storage.borrow_mut();
+        if let Some(existing_course) = storage.get(&id) {
+            // Patch the current values so they go through the same validation as a new course
+            let mut payload = CoursePayload::from(&existing_course);
+            patch.apply_to(&mut payload);
+            validate_course_payload(&payload)?;
+
+            let mut updated_course = existing_course.clone();
+            if payload.teacher_id != existing_course.teacher_id {
+                let teacher = check_course_teacher(Some(id), payload.teacher_id)?;
+                set_course_teacher("update_course", &mut updated_course, teacher);
+            }
+
+            updated_course.name = payload.name;
+            updated_course.description = payload.description;
+
+            // Note: Student IDs, syllabus, and materials are not updated here
+            updated_course.metadata.touch();
+            storage.insert(id, updated_course.clone());
+            audit(
+                "update_course",
+                "Course",
+                Some(id.into()),
+                Some(&existing_course),
+                Some(&updated_course),
+            );
+
+            Ok(updated_course)
+        } else {
+            Err(Error::not_found("Course", id))
+        }
+    })
+}
+
+/// Collects the records that refer to a course.
+fn course_references(course: &Course) -> Vec<RecordRef> {
+    let mut references: Vec<RecordRef> =
+        course.student_ids.iter().map(|student_id| RecordRef::new("Student", *student_id)).collect();
+    if let Some(teacher_id) = course.teacher_id.filter(|teacher_id| {
+        TEACHER_STORAGE
+            .with(|storage| storage.borrow().get(teacher_id))
+            .is_some_and(|teacher| teacher.assigned_courses.contains(&course.id))
+    }) {
+        references.push(RecordRef::new("Teacher", teacher_id));
+    }
+    for classroom in classrooms_of_course(course.id) {
+        references.push(RecordRef::new("Classroom", classroom.id));
+    }
+    for slot_id in index_ids(&COURSE_SLOT_INDEX, course.id) {
+        references.push(RecordRef::new("ScheduleSlot", slot_id));
+    }
+    references
+}
+
+/// Returns the classrooms whose current course is the course.
+fn classrooms_of_course(course_id: CourseId) -> Vec<Classroom> {
+    CLASSROOM_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .iter()
+            .map(|(_, classroom)| classroom)
+            .filter(|classroom| classroom.current_course_id == Some(course_id))
+            .collect()
+    })
+}
+
+/// Archives a course after clearing every reference to it: its students are dropped, its
+/// teacher is unassigned, classrooms lose it as their current course and its slots are removed.
+fn archive_course(endpoint: &str, mut course: Course, reason: &str, summary: &mut DeleteSummary) {
+    let id = course.id;
+    for slot in slots_from_index(&COURSE_SLOT_INDEX, id) {
+        remove_slot(endpoint, &slot);
+        summary.removed_slots.push(slot.id);
+    }
+    for student_id in std::mem::take(&mut course.student_ids) {
+        if let Some(mut student) = STUDENT_STORAGE.with(|storage| storage.borrow().get(&student_id)) {
+            let existing_student = student.clone();
+            student.enrolled_courses.retain(|course_id| *course_id != id);
+            student.metadata.touch();
+            STUDENT_STORAGE.with(|storage| storage.borrow_mut().insert(student_id, student.clone()));
+            audit(endpoint, "Student", Some(student_id.into()), Some(&existing_student), Some(&student));
+            summary.updated("Student", student_id);
+        }
+    }
+    if let Some(mut teacher) = course
+        .teacher_id
+        .take()
+        .and_then(|teacher_id| TEACHER_STORAGE.with(|storage| storage.borrow().get(&teacher_id)))
+        .filter(|teacher| teacher.assigned_courses.contains(&id))
+    {
+        let existing_teacher = teacher.clone();
+        teacher.assigned_courses.retain(|course_id| *course_id != id);
+        summary.updated("Teacher", teacher.id);
+        store_teacher(endpoint, &existing_teacher, teacher);
+    }
+    for mut classroom in classrooms_of_course(id) {
+        let existing_classroom = classroom.clone();
+        classroom.current_course_id = None;
+        classroom.metadata.touch();
+        CLASSROOM_STORAGE.with(|storage| storage.borrow_mut().insert(classroom.id, classroom.clone()));
+        audit(endpoint, "Classroom", Some(classroom.id.into()), Some(&existing_classroom), Some(&classroom));
+        summary.updated("Classroom", classroom.id);
+    }
+    archive_record(endpoint, &COURSE_STORAGE, &COURSE_ARCHIVE, id, course, reason);
+    summary.archived.push(RecordRef::new("Course", id));
+}
+
+/// Deletes a course by archiving it with the reason, treating its students, teacher, classrooms
+/// and schedule slots according to the policy (`Restrict` by default).
+#[ic_cdk::update]
+fn delete_course(id: CourseId, reason: String, policy: Option<DeletePolicy>) -> Result<DeleteSummary, Error> {
+    require_registrar()?;
+    validate_archive_reason(&reason)?;
+    let course = COURSE_STORAGE
+        .with(|storage| storage.borrow().get(&id))
+        .ok_or_else(|| Error::not_found("Course", id))?;
+    ensure_deletable(policy.unwrap_or_default(), "Course", id, &course_references(&course))?;
+
+    let mut summary = DeleteSummary::default();
+    archive_course("delete_course", course, &reason, &mut summary);
+
+    Ok(summary)
+}
+
+/// Restores an archived course.
+#[ic_cdk::update]
+fn restore_course(id: CourseId) -> Result<Course, Error> {
+    require_registrar()?;
+    restore_record("restore_course", &COURSE_STORAGE, &COURSE_ARCHIVE, id, |_| Ok(()))
+}
+
+/// Permanently removes an archived course.
+#[ic_cdk::update]
+fn purge_course(id: CourseId) -> Result<(), Error> {
+    require_admin()?;
+    purge_record("purge_course", &COURSE_ARCHIVE, id).map(|_| ())
+}
+
+/// Ensures the current course of a classroom, if any, exists.
+fn check_current_course(course_id: Option<CourseId>) -> Result<(), Error> {
+    match course_id {
+        Some(course_id) if !COURSE_STORAGE.with(|storage| storage.borrow().contains_key(&course_id)) => {
+            Err(Error::not_found("Course", course_id))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn validate_classroom_payload(payload: &ClassroomPayload) -> Result<(), Error> {
+    Validator::new()
+        .required("name", &payload.name, MAX_NAME_LENGTH)
+        .required("location", &payload.location, MAX_NAME_LENGTH)
+        .check("capacity", payload.capacity > 0, "Must be greater than zero")
+        .finish()
+}
+
+/// Adds a new classroom with the provided payload.
+#[ic_cdk::update]
+fn add_classroom(payload: ClassroomPayload) -> Result<Classroom, Error> {
+    require_registrar()?;
+    validate_classroom_payload(&payload)?;
+    check_current_course(payload.current_course_id)?;
+
+    let id = ClassroomId(next_id(&CLASSROOM_ID_COUNTER));
+
+    let classroom = Classroom {
+        id,
+        name: payload.name,
+        location: payload.location,
+        capacity: payload.capacity,
+        current_course_id: payload.current_course_id,
+        equipment: Vec::new(), // Initial empty equipment list
+        metadata: Metadata::new(),
+    };
+
+    CLASSROOM_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(id, classroom.clone());
+    });
+    audit("add_classroom", "Classroom", Some(id.into()), None, Some(&classroom));
+
+    Ok(classroom)
+}
+
+/// Retrieves information about a classroom based on the provided ID.
+#[ic_cdk::query]
+fn get_classroom(id: ClassroomId) -> Result<Classroom, Error> {
+    require_member()?;
+    CLASSROOM_STORAGE.with(|storage| {
+        match storage.borrow().get(&id) {
+            Some(classroom) => Ok(classroom.clone()),
+            None => Err(Error::not_found("Classroom", id)),
+        }
+    })
+}
+
+/// Updates the provided fields of a classroom, leaving the others unchanged.
+#[ic_cdk::update]
+fn update_classroom(id: ClassroomId, patch: ClassroomPatch) -> Result<Classroom, Error> {
+    require_registrar()?;
+    CLASSROOM_STORAGE.with(|storage| {
+        let mut storage = storage.borrow_mut();
+        if let Some(existing_classroom) = storage.get(&id) {
+            // Patch the current values so they go through the same validation as a new classroom
+            let mut payload = ClassroomPayload::from(&existing_classroom);
+            patch.apply_to(&mut payload);
+            validate_classroom_payload(&payload)?;
+            if payload.current_course_id != existing_classroom.current_course_id {
+                check_current_course(payload.current_course_id)?;
+            }
+
+            let mut updated_classroom = existing_classroom.clone();
+
+            updated_classroom.name = payload.name;
+            updated_classroom.location = payload.location;
+            updated_classroom.capacity = payload.capacity;
+            updated_classroom.current_course_id = payload.current_course_id;
+
+            // Equipment is not updated here
+            updated_classroom.metadata.touch();
+            storage.insert(id, updated_classroom.clone());
+            audit(
+                "update_classroom",
+                "Classroom",
+                Some(id.into()),
+                Some(&existing_classroom),
+                Some(&updated_classroom),
+            );
+
+            Ok(updated_classroom)
+        } else {
+            Err(Error::not_found("Classroom", id))
+        }
+    })
+}
+
+/// Deletes a classroom by archiving it with the reason, treating the schedule slots booked in it
+/// according to the policy (`Restrict` by default).
+#[ic_cdk::update]
+fn delete_classroom(
+    id: ClassroomId,
+    reason: String,
+    policy: Option<DeletePolicy>,
+) -> Result<DeleteSummary, Error> {
+    require_registrar()?;
+    validate_archive_reason(&reason)?;
+    let classroom = CLASSROOM_STORAGE
+        .with(|storage| storage.borrow().get(&id))
+        .ok_or_else(|| Error::not_found("Classroom", id))?;
+    let slots = slots_from_index(&CLASSROOM_SLOT_INDEX, id);
+    let references: Vec<RecordRef> =
+        slots.iter().map(|slot| RecordRef::new("ScheduleSlot", slot.id)).collect();
+    ensure_deletable(policy.unwrap_or_default(), "Classroom", id, &references)?;
+
+    let mut summary = DeleteSummary {
+        archived: vec![RecordRef::new("Classroom", id)],
+        ..Default::default()
+    };
+    for slot in slots {
+        remove_slot("delete_classroom", &slot);
+        if let Some(course) = COURSE_STORAGE.with(|storage| storage.borrow().get(&slot.course_id)) {
+            refresh_teacher_schedule("delete_classroom", &course);
+        }
+        summary.removed_slots.push(slot.id);
+    }
+    archive_record("delete_classroom", &CLASSROOM_STORAGE, &CLASSROOM_ARCHIVE, id, classroom, &reason);
+
+    Ok(summary)
+}
+
+/// Restores an archived classroom.
+#[ic_cdk::update]
+fn restore_classroom(id: ClassroomId) -> Result<Classroom, Error> {
+    require_registrar()?;
+    restore_record("restore_classroom", &CLASSROOM_STORAGE, &CLASSROOM_ARCHIVE, id, |_| Ok(()))
+}
+
+/// Permanently removes an archived classroom.
+#[ic_cdk::update]
+fn purge_classroom(id: ClassroomId) -> Result<(), Error> {
+    require_admin()?;
+    purge_record("purge_classroom", &CLASSROOM_ARCHIVE, id).map(|_| ())
+}
+
+/// Enrolls a student in a course, updating both the student and the course roster.
+#[ic_cdk::update]
+fn enroll_student(student_id: StudentId, course_id: CourseId) -> Result<Course, Error> {
+    require_registrar()?;
+    let mut student = STUDENT_STORAGE
+        .with(|storage| storage.borrow().get(&student_id))
+        .ok_or_else(|| Error::not_found("Student", student_id))?;
+    let mut course = COURSE_STORAGE
+        .with(|storage| storage.borrow().get(&course_id))
+        .ok_or_else(|| Error::not_found("Course", course_id))?;
+
+    if course.student_ids.contains(&student_id) || student.enrolled_courses.contains(&course_id) {
+        return Err(Error::conflict(
+            "Course",
+            format!("Student {} is already enrolled in course {}", student_id, course_id),
+        ));
+    }
+    if course.student_ids.len() >= MAX_STUDENTS_PER_COURSE {
+        return Err(Error::CapacityExceeded {
+            entity: "Course".to_string(),
+            id: course_id.into(),
+            capacity: MAX_STUDENTS_PER_COURSE as u32,
+        });
+    }
+    if student.enrolled_courses.len() >= MAX_COURSES_PER_STUDENT {
+        return Err(Error::CapacityExceeded {
+            entity: "Student".to_string(),
+            id: student_id.into(),
+            capacity: MAX_COURSES_PER_STUDENT as u32,
+        });
+    }
+    if let Some(classroom) = slots_from_index(&COURSE_SLOT_INDEX, course_id)
+        .iter()
+        .filter_map(|slot| CLASSROOM_STORAGE.with(|storage| storage.borrow().get(&slot.classroom_id)))
+        .find(|classroom| course.student_ids.len() >= classroom.capacity as usize)
+    {
+        return Err(Error::CapacityExceeded {
+            entity: "Classroom".to_string(),
+            id: classroom.id.into(),
+            capacity: classroom.capacity,
+        });
+    }
+
+    // Both records are validated above, so the two inserts below cannot fail halfway.
+    let existing_student = student.clone();
+    let existing_course = course.clone();
+    student.enrolled_courses.push(course_id);
+    course.student_ids.push(student_id);
+
+    student.metadata.touch();
+    course.metadata.touch();
+    STUDENT_STORAGE.with(|storage| storage.borrow_mut().insert(student_id, student.clone()));
+    COURSE_STORAGE.with(|storage| storage.borrow_mut().insert(course_id, course.clone()));
+    audit("enroll_student", "Student", Some(student_id.into()), Some(&existing_student), Some(&student));
+    audit("enroll_student", "Course", Some(course_id.into()), Some(&existing_course), Some(&course));
+
+    Ok(course)
+}
+
+/// Drops a student from a course, updating both the student and the course roster.
+#[ic_cdk::update]
+fn drop_student(student_id: StudentId, course_id: CourseId) -> Result<Course, Error> {
+    require_registrar()?;
+    let mut student = STUDENT_STORAGE
+        .with(|storage| storage.borrow().get(&student_id))
+        .ok_or_else(|| Error::not_found("Student", student_id))?;
+    let mut course = COURSE_STORAGE
+        .with(|storage| storage.borrow().get(&course_id))
+        .ok_or_else(|| Error::not_found("Course", course_id))?;
+
+    if !course.student_ids.contains(&student_id) && !student.enrolled_courses.contains(&course_id) {
+        return Err(Error::conflict(
+            "Course",
+            format!("Student {} is not enrolled in course {}", student_id, course_id),
+        ));
+    }
+
+    // Remove the link from both sides so a half-written enrollment is repaired as well.
+    let existing_student = student.clone();
+    let existing_course = course.clone();
+    student.enrolled_courses.retain(|id| *id != course_id);
+    course.student_ids.retain(|id| *id != student_id);
+
+    student.metadata.touch();
+    course.metadata.touch();
+    STUDENT_STORAGE.with(|storage| storage.borrow_mut().insert(student_id, student.clone()));
+    COURSE_STORAGE.with(|storage| storage.borrow_mut().insert(course_id, course.clone()));
+    audit("drop_student", "Student", Some(student_id.into()), Some(&existing_student), Some(&student));
+    audit("drop_student", "Course", Some(course_id.into()), Some(&existing_course), Some(&course));
+
+    Ok(course)
+}
+
+/// Validates a guardian payload. A phone number or an email address is required unless
+/// `require_contact` is false, which lets guardians migrated without either be updated.
+fn validate_guardian_payload(payload: &GuardianPayload, require_contact: bool) -> Result<(), Error> {
+    let mut validator = Validator::new();
+    validator
+        .required("name", &payload.name, MAX_NAME_LENGTH)
+        .check(
+            "phone",
+            !require_contact || !payload.phone.trim().is_empty() || !payload.email.trim().is_empty(),
+            "A phone number or an email address is required",
+        )
+        .max_length("notes", &payload.notes, MAX_LONG_TEXT_LENGTH);
+    if !payload.phone.trim().is_empty() {
+        validator.phone("phone", &payload.phone);
+    }
+    if !payload.email.trim().is_empty() {
+        validator.email("email", &payload.email);
+    }
+    validator.finish()
+}
+
+/// Ensures the principal can sign in as the guardian: it is not anonymous, not the
+/// principal of another guardian and has room for another role.
+fn ensure_guardian_principal_available(
+    principal: Principal,
+    guardian_id: Option<GuardianId>,
+) -> Result<(), Error> {
+    if principal == Principal::anonymous() {
+        return Err(Error::invalid_input("principal", "The anonymous principal cannot sign in as a guardian"));
+    }
+    let roles = roles_of(principal);
+    for role in &roles {
+        if let Role::Guardian { guardian_id: other } = *role {
+            if Some(other) != guardian_id {
+                return Err(Error::conflict(
+                    "Guardian",
+                    format!("The principal already belongs to guardian {}", other),
+                ));
+            }
+        }
+    }
+    if roles.len() >= MAX_ROLES_PER_PRINCIPAL {
+        return Err(Error::invalid_input(
+            "principal",
+            &format!("A principal can hold at most {} roles", MAX_ROLES_PER_PRINCIPAL),
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the IDs of the students linked to the guardian.
+fn guardian_student_ids(guardian_id: GuardianId) -> Vec<StudentId> {
+    index_ids(&GUARDIAN_STUDENT_INDEX, guardian_id).into_iter().map(StudentId).collect()
+}
+
+/// Returns the guardians linked to the student.
+fn guardians_of(student_id: StudentId) -> Vec<Guardian> {
+    let ids = index_ids(&STUDENT_GUARDIAN_INDEX, student_id);
+    GUARDIAN_STORAGE.with(|storage| {
+        let storage = storage.borrow();
+        ids.into_iter().filter_map(|id| storage.get(&GuardianId(id))).collect()
+    })
+}
+
+fn insert_guardian_link(student_id: StudentId, guardian_id: GuardianId) {
+    STUDENT_GUARDIAN_INDEX.with(|index| index.borrow_mut().insert((student_id, guardian_id.into()), ()));
+    GUARDIAN_STUDENT_INDEX.with(|index| index.borrow_mut().insert((guardian_id, student_id.into()), ()));
+}
+
+fn remove_guardian_link(student_id: StudentId, guardian_id: GuardianId) {
+    STUDENT_GUARDIAN_INDEX.with(|index| index.borrow_mut().remove(&(student_id, guardian_id.into())));
+    GUARDIAN_STUDENT_INDEX.with(|index| index.borrow_mut().remove(&(guardian_id, student_id.into())));
+}
+
+/// Adds a new guardian with the provided payload.
+///
+/// A guardian with a principal is granted the guardian role for that principal.
+#[ic_cdk::update]
+fn add_guardian(payload: GuardianPayload) -> Result<Guardian, Error> {
+    require_registrar()?;
+    validate_guardian_payload(&payload, true)?;
+    if let Some(principal) = payload.principal {
+        ensure_guardian_principal_available(principal, None)?;
+    }
+
+    let id = GuardianId(next_id(&GUARDIAN_ID_COUNTER));
+
+    let guardian = Guardian {
+        id,
+        name: payload.name,
+        relationship: payload.relationship,
+        phone: payload.phone,
+        email: payload.email,
+        principal: payload.principal,
+        has_custody: payload.has_custody,
+        can_pick_up: payload.can_pick_up,
+        notes: payload.notes,
+        metadata: Metadata::new(),
+    };
+
+    GUARDIAN_STORAGE.with(|storage| storage.borrow_mut().insert(id, guardian.clone()));
+    if let Some(principal) = guardian.principal {
+        add_role("add_guardian", principal, Role::Guardian { guardian_id: id })?;
+    }
+    audit("add_guardian", "Guardian", Some(id.into()), None, Some(&guardian));
+
+    Ok(guardian)
+}
+
+/// Retrieves information about a guardian based on the provided ID.
+#[ic_cdk::query]
+fn get_guardian(id: GuardianId) -> Result<Guardian, Error> {
+    require_guardian_access(id)?;
+    GUARDIAN_STORAGE
+        .with(|storage| storage.borrow().get(&id))
+        .ok_or_else(|| Error::not_found("Guardian", id))
+}
+
+/// Updates the provided fields of a guardian, leaving the others unchanged.
+///
+/// Changing the principal moves the guardian role to the new principal, and removing it
+/// revokes the role.
+#[ic_cdk::update]
+fn update_guardian(id: GuardianId, patch: GuardianPatch) -> Result<Guardian, Error> {
+    require_registrar()?;
+    let existing_guardian = GUARDIAN_STORAGE
+        .with(|storage| storage.borrow().get(&id))
+        .ok_or_else(|| Error::not_found("Guardian", id))?;
+
+    let mut payload = GuardianPayload::from(&existing_guardian);
+    patch.apply_to(&mut payload);
+    let had_contact =
+        !existing_guardian.phone.trim().is_empty() || !existing_guardian.email.trim().is_empty();
+    validate_guardian_payload(&payload, had_contact)?;
+    let principal_changed = payload.principal != existing_guardian.principal;
+    if let Some(principal) = payload.principal.filter(|_| principal_changed) {
+        ensure_guardian_principal_available(principal, Some(id))?;
+    }
+
+    let mut updated_guardian = Guardian {
+        id,
+        name: payload.name,
+        relationship: payload.relationship,
+        phone: payload.phone,
+        email: payload.email,
+        principal: payload.principal,
+        has_custody: payload.has_custody,
+        can_pick_up: payload.can_pick_up,
+        notes: payload.notes,
+        metadata: existing_guardian.metadata,
+    };
+    updated_guardian.metadata.touch();
+
+    GUARDIAN_STORAGE.with(|storage| storage.borrow_mut().insert(id, updated_guardian.clone()));
+    if principal_changed {
+        if let Some(principal) = existing_guardian.principal {
+            remove_role("update_guardian", principal, Role::Guardian { guardian_id: id });
+        }
+        if let Some(principal) = updated_guardian.principal {
+            add_role("update_guardian", principal, Role::Guardian { guardian_id: id })?;
+        }
+    }
+    audit(
+        "update_guardian",
+        "Guardian",
+        Some(id.into()),
+        Some(&existing_guardian),
+        Some(&updated_guardian),
+    );
+
+    Ok(updated_guardian)
+}
+
+/// Deletes a guardian by archiving it with the reason. An archived guardian keeps its links and
+/// its role but is hidden from the students' guardians and cannot use the guardian portal.
+#[ic_cdk::update]
+fn delete_guardian(id: GuardianId, reason: String) -> Result<(), Error> {
+    require_registrar()?;
+    validate_archive_reason(&reason)?;
+    let guardian = GUARDIAN_STORAGE
+        .with(|storage| storage.borrow().get(&id))
+        .ok_or_else(|| Error::not_found("Guardian", id))?;
+    archive_record("delete_guardian", &GUARDIAN_STORAGE, &GUARDIAN_ARCHIVE, id, guardian, &reason);
+    Ok(())
+}
+
+/// Restores an archived guardian.
+#[ic_cdk::update]
+fn restore_guardian(id: GuardianId) -> Result<Guardian, Error> {
+    require_registrar()?;
+    restore_record("restore_guardian", &GUARDIAN_STORAGE, &GUARDIAN_ARCHIVE, id, |_| Ok(()))
+}
+
+/// Permanently removes an archived guardian, unlinking it from its students and revoking its role.
+#[ic_cdk::update]
+fn purge_guardian(id: GuardianId) -> Result<(), Error> {
+    require_admin()?;
+    let archived = purge_record("purge_guardian", &GUARDIAN_ARCHIVE, id)?;
+    for student_id in guardian_student_ids(id) {
+        remove_guardian_link(student_id, id);
+    }
+    if let Some(principal) = archived.record.principal {
+        remove_role("purge_guardian", principal, Role::Guardian { guardian_id: id });
+    }
+    Ok(())
+}
+
+/// Links a guardian to a student and returns the guardians of the student.
+#[ic_cdk::update]
+fn link_guardian(student_id: StudentId, guardian_id: GuardianId) -> Result<Vec<Guardian>, Error> {
+    require_registrar()?;
+    if !STUDENT_STORAGE.with(|storage| storage.borrow().contains_key(&student_id)) {
+        return Err(Error::not_found("Student", student_id));
+    }
+    if !GUARDIAN_STORAGE.with(|storage| storage.borrow().contains_key(&guardian_id)) {
+        return Err(Error::not_found("Guardian", guardian_id));
+    }
+
+    let guardian_ids = index_ids(&STUDENT_GUARDIAN_INDEX, student_id);
+    if guardian_ids.contains(&guardian_id.into()) {
+        return Err(Error::conflict(
+            "Guardian",
+            format!("Guardian {} is already linked to student {}", guardian_id, student_id),
+        ));
+    }
+    if guardian_ids.len() >= MAX_GUARDIANS_PER_STUDENT {
+        return Err(Error::CapacityExceeded {
+            entity: "Student".to_string(),
+            id: student_id.into(),
+            capacity: MAX_GUARDIANS_PER_STUDENT as u32,
+        });
+    }
+
+    insert_guardian_link(student_id, guardian_id);
+    let linked_ids = index_ids(&STUDENT_GUARDIAN_INDEX, student_id);
+    audit("link_guardian", "Student", Some(student_id.into()), Some(&guardian_ids), Some(&linked_ids));
+
+    Ok(guardians_of(student_id))
+}
+
+/// Unlinks a guardian from a student and returns the remaining guardians of the student.
+#[ic_cdk::update]
+fn unlink_guardian(student_id: StudentId, guardian_id: GuardianId) -> Result<Vec<Guardian>, Error> {
+    require_registrar()?;
+    let guardian_ids = index_ids(&STUDENT_GUARDIAN_INDEX, student_id);
+    if !guardian_ids.contains(&guardian_id.into()) {
+        return Err(Error::conflict(
+            "Guardian",
+            format!("Guardian {} is not linked to student {}", guardian_id, student_id),
+        ));
+    }
+
+    remove_guardian_link(student_id, guardian_id);
+    let linked_ids = index_ids(&STUDENT_GUARDIAN_INDEX, student_id);
+    audit("unlink_guardian", "Student", Some(student_id.into()), Some(&guardian_ids), Some(&linked_ids));
+
+    Ok(guardians_of(student_id))
+}
+
+/// Retrieves the guardians linked to a student.
+#[ic_cdk::query]
+fn get_guardians_for_student(student_id: StudentId) -> Result<Vec<Guardian>, Error> {
+    require_student_access(student_id)?;
+    if !STUDENT_STORAGE.with(|storage| storage.borrow().contains_key(&student_id)) {
+        return Err(Error::not_found("Student", student_id));
+    }
+    Ok(guardians_of(student_id))
+}
+
+fn validate_attendance_note(note: &str) -> Result<(), Error> {
+    Validator::new()
+        .max_length("note", note, MAX_ATTENDANCE_NOTE_LENGTH)
+        .finish()
+}
+
+/// Returns the child IDs stored under an owner in an index.
+fn index_ids<K: EntityId>(index: &'static LocalKey<RefCell<IdIndex<K>>>, owner_id: K) -> Vec<u64> {
+    index.with(|index| {
+        index
+            .borrow()
+            .range((owner_id, 0)..=(owner_id, u64::MAX))
+            .map(|((_, id), _)| id)
+            .collect()
+    })
+}
+
+/// Collects the attendance records referenced by an index, filtered by session date.
+fn attendance_from_index<K: EntityId>(
+    index: &'static LocalKey<RefCell<IdIndex<K>>>,
+    owner_id: K,
+    from: Option<Date>,
+    to: Option<Date>,
+) -> Vec<AttendanceRecord> {
+    let ids = index_ids(index, owner_id);
+
+    let mut records: Vec<AttendanceRecord> = ATTENDANCE_STORAGE.with(|storage| {
+        let storage = storage.borrow();
+        ids.iter()
+            .filter_map(|id| storage.get(id))
+            .filter(|record| record.session_date.is_within(from, to))
+            .collect()
+    });
+    records.sort_by_key(|record| (record.session_date, record.id));
+    records
+}
+
+/// Marks the attendance of an enrolled student for a course session.
+#[ic_cdk::update]
+fn mark_attendance(payload: AttendancePayload) -> Result<AttendanceRecord, Error> {
+    require_course_access(payload.course_id)?;
+    if !payload.session_date.is_valid() {
+        return Err(Error::invalid_input("session_date", "Session date is not a valid calendar date"));
+    }
+    validate_attendance_note(&payload.note)?;
+
+    let student = STUDENT_STORAGE
+        .with(|storage| storage.borrow().get(&payload.student_id))
+        .ok_or_else(|| Error::not_found("Student", payload.student_id))?;
+    if !COURSE_STORAGE.with(|storage| storage.borrow().contains_key(&payload.course_id)) {
+        return Err(Error::not_found("Course", payload.course_id));
+    }
+    if !student.enrolled_courses.contains(&payload.course_id) {
+        return Err(Error::invalid_input("student_id", "Student is not enrolled in the course"));
+    }
+
+    let already_marked = attendance_from_index(
+        &STUDENT_ATTENDANCE_INDEX,
+        payload.student_id,
+        Some(payload.session_date),
+        Some(payload.session_date),
+    )
+    .iter()
+    .any(|record| record.course_id == payload.course_id);
+    if already_marked {
+        return Err(Error::conflict(
+            "AttendanceRecord",
+            format!(
+                "Attendance for student {} in course {} is already marked for this date",
+                payload.student_id, payload.course_id
+            ),
+        ));
+    }
+
+    let id = next_id(&ID_COUNTER);
+
+    let record = AttendanceRecord {
+        id,
+        student_id: payload.student_id,
+        course_id: payload.course_id,
+        session_date: payload.session_date,
+        status: payload.status,
+        note: payload.note,
+        recorded_by: caller(),
+        recorded_at: time(),
+    };
+
+    ATTENDANCE_STORAGE.with(|storage| storage.borrow_mut().insert(id, record.clone()));
+    STUDENT_ATTENDANCE_INDEX.with(|index| index.borrow_mut().insert((record.student_id, id), ()));
+    COURSE_ATTENDANCE_INDEX.with(|index| index.borrow_mut().insert((record.course_id, id), ()));
+    audit("mark_attendance", "AttendanceRecord", Some(id), None, Some(&record));
+
+    Ok(record)
+}
+
+/// Corrects the status and note of an existing attendance record.
+#[ic_cdk::update]
+fn correct_attendance(id: u64, correction: AttendanceCorrection) -> Result<AttendanceRecord, Error> {
+    require_staff()?;
+    let course_id = ATTENDANCE_STORAGE
+        .with(|storage| storage.borrow().get(&id))
+        .ok_or_else(|| Error::not_found("AttendanceRecord", id))?
+        .course_id;
+    require_course_access(course_id)?;
+    validate_attendance_note(&correction.note)?;
+
+    ATTENDANCE_STORAGE.with(|storage| {
+        let mut storage = storage.borrow_mut();
+        if let Some(mut record) = storage.get(&id) {
+            let existing_record = record.clone();
+            record.status = correction.status;
+            record.note = correction.note;
+            record.recorded_by = caller();
+            record.recorded_at = time();
+
+            storage.insert(id, record.clone());
+            audit("correct_attendance", "AttendanceRecord", Some(id), Some(&existing_record), Some(&record));
+
+            Ok(record)
+        } else {
+            Err(Error::not_found("AttendanceRecord", id))
+        }
+    })
+}
+
+/// Retrieves a student's attendance, optionally limited to an inclusive date range.
+#[ic_cdk::query]
+fn get_student_attendance(
+    student_id: StudentId,
+    from: Option<Date>,
+    to: Option<Date>,
+) -> Result<Vec<AttendanceRecord>, Error> {
+    require_student_access(student_id)?;
+    if !STUDENT_STORAGE.with(|storage| storage.borrow().contains_key(&student_id)) {
+        return Err(Error::not_found("Student", student_id));
+    }
+    Ok(attendance_from_index(&STUDENT_ATTENDANCE_INDEX, student_id, from, to))
+}
+
+/// Retrieves a course's attendance, optionally limited to an inclusive date range.
+#[ic_cdk::query]
+fn get_course_attendance(
+    course_id: CourseId,
+    from: Option<Date>,
+    to: Option<Date>,
+) -> Result<Vec<AttendanceRecord>, Error> {
+    require_course_access(course_id)?;
+    if !COURSE_STORAGE.with(|storage| storage.borrow().contains_key(&course_id)) {
+        return Err(Error::not_found("Course", course_id));
+    }
+    Ok(attendance_from_index(&COURSE_ATTENDANCE_INDEX, course_id, from, to))
+}
+
+fn validate_grade(score: f64, max_score: f64, weight: f64) -> Result<(), Error> {
+    Validator::new()
+        .check("max_score", max_score.is_finite() && max_score > 0.0, "Must be greater than zero")
+        .check(
+            "score",
+            score.is_finite() && score >= 0.0 && score <= max_score,
+            "Must be between zero and the max score",
+        )
+        .check("weight", weight.is_finite() && weight > 0.0, "Must be greater than zero")
+        .finish()
+}
+
+/// Ensures the teacher exists and teaches the course.
+fn ensure_teaches_course(teacher_id: TeacherId, course_id: CourseId) -> Result<(), Error> {
+    if !TEACHER_STORAGE.with(|storage| storage.borrow().contains_key(&teacher_id)) {
+        return Err(Error::not_found("Teacher", teacher_id));
+    }
+    let course = COURSE_STORAGE
+        .with(|storage| storage.borrow().get(&course_id))
+        .ok_or_else(|| Error::not_found("Course", course_id))?;
+    if course.teacher_id != Some(teacher_id) {
+        return Err(Error::unauthorized("Only the teacher of the course can grade it"));
+    }
+    Ok(())
+}
+
+/// Collects the performance records referenced by an index.
+fn performance_from_index<K: EntityId>(
+    index: &'static LocalKey<RefCell<IdIndex<K>>>,
+    owner_id: K,
+) -> Vec<PerformanceRecord> {
+    let ids = index_ids(index, owner_id);
+    PERFORMANCE_STORAGE.with(|storage| {
+        let storage = storage.borrow();
+        ids.iter().filter_map(|id| storage.get(id)).collect()
+    })
+}
+
+/// Records a grade for an enrolled student, on behalf of the teacher of the course.
+#[ic_cdk::update]
+fn record_grade(payload: GradePayload) -> Result<PerformanceRecord, Error> {
+    require_teacher(payload.teacher_id)?;
+    Validator::new()
+        .required("assessment_name", &payload.assessment_name, MAX_ASSESSMENT_NAME_LENGTH)
+        .finish()?;
+    validate_grade(payload.score, payload.max_score, payload.weight)?;
+    ensure_teaches_course(payload.teacher_id, payload.course_id)?;
+
+    let student = STUDENT_STORAGE
+        .with(|storage| storage.borrow().get(&payload.student_id))
+        .ok_or_else(|| Error::not_found("Student", payload.student_id))?;
+    if !student.enrolled_courses.contains(&payload.course_id) {
+        return Err(Error::invalid_input("student_id", "Student is not enrolled in the course"));
+    }
+
+    let already_graded = performance_from_index(&STUDENT_PERFORMANCE_INDEX, payload.student_id)
+        .iter()
+        .any(|record| {
+            record.course_id == payload.course_id && record.assessment_name == payload.assessment_name
+        });
+    if already_graded {
+        return Err(Error::conflict(
+            "PerformanceRecord",
+            format!(
+                "Student {} already has a grade for '{}' in course {}",
+                payload.student_id, payload.assessment_name, payload.course_id
+            ),
+        ));
+    }
+
+    let id = next_id(&ID_COUNTER);
+
+    let record = PerformanceRecord {
+        id,
+        student_id: payload.student_id,
+        course_id: payload.course_id,
+        assessment_name: payload.assessment_name,
+        score: payload.score,
+        max_score: payload.max_score,
+        weight: payload.weight,
+        graded_by: payload.teacher_id,
+        graded_at: time(),
+    };
+
+    PERFORMANCE_STORAGE.with(|storage| storage.borrow_mut().insert(id, record.clone()));
+    STUDENT_PERFORMANCE_INDEX.with(|index| index.borrow_mut().insert((record.student_id, id), ()));
+    COURSE_PERFORMANCE_INDEX.with(|index| index.borrow_mut().insert((record.course_id, id), ()));
+    audit("record_grade", "PerformanceRecord", Some(id), None, Some(&record));
+
+    Ok(record)
+}
+
+/// Amends the score and weighting of an existing grade.
+#[ic_cdk::update]
+fn amend_grade(id: u64, amendment: GradeAmendment) -> Result<PerformanceRecord, Error> {
+    require_teacher(amendment.teacher_id)?;
+    validate_grade(amendment.score, amendment.max_score, amendment.weight)?;
+
+    let mut record = PERFORMANCE_STORAGE
+        .with(|storage| storage.borrow().get(&id))
+        .ok_or_else(|| Error::not_found("PerformanceRecord", id))?;
+    ensure_teaches_course(amendment.teacher_id, record.course_id)?;
+
+    let existing_record = record.clone();
+    record.score = amendment.score;
+    record.max_score = amendment.max_score;
+    record.weight = amendment.weight;
+    record.graded_by = amendment.teacher_id;
+    record.graded_at = time();
+
+    PERFORMANCE_STORAGE.with(|storage| storage.borrow_mut().insert(id, record.clone()));
+    audit("amend_grade", "PerformanceRecord", Some(id), Some(&existing_record), Some(&record));
+
+    Ok(record)
+}
+
+/// Returns a student's performance records, optionally limited to a single course.
+fn student_grades(student_id: StudentId, course_id: Option<CourseId>) -> Vec<PerformanceRecord> {
+    let mut records = performance_from_index(&STUDENT_PERFORMANCE_INDEX, student_id);
+    records.retain(|record| course_id.map_or(true, |course_id| record.course_id == course_id));
+    records
+}
+
+/// Retrieves a student's gradebook, optionally limited to a single course.
+#[ic_cdk::query]
+fn get_student_gradebook(
+    student_id: StudentId,
+    course_id: Option<CourseId>,
+) -> Result<Vec<PerformanceRecord>, Error> {
+    require_student_access(student_id)?;
+    if !STUDENT_STORAGE.with(|storage| storage.borrow().contains_key(&student_id)) {
+        return Err(Error::not_found("Student", student_id));
+    }
+    Ok(student_grades(student_id, course_id))
+}
+
+/// Retrieves the gradebook of a course across all of its students.
+#[ic_cdk::query]
+fn get_course_gradebook(course_id: CourseId) -> Result<Vec<PerformanceRecord>, Error> {
+    require_course_access(course_id)?;
+    if !COURSE_STORAGE.with(|storage| storage.borrow().contains_key(&course_id)) {
+        return Err(Error::not_found("Course", course_id));
+    }
+    Ok(performance_from_index(&COURSE_PERFORMANCE_INDEX, course_id))
+}
+
+/// Computes the weighted average of a student's grades in a course, if any were recorded.
+fn compute_course_average(student_id: StudentId, course_id: CourseId) -> Option<CourseAverage> {
+    let records: Vec<PerformanceRecord> = performance_from_index(&STUDENT_PERFORMANCE_INDEX, student_id)
+        .into_iter()
+        .filter(|record| record.course_id == course_id)
+        .collect();
+    let total_weight: f64 = records.iter().map(|record| record.weight).sum();
+    if records.is_empty() || total_weight <= 0.0 {
+        return None;
+    }
+
+    let weighted_sum: f64 = records
+        .iter()
+        .map(|record| record.score / record.max_score * record.weight)
+        .sum();
+    let percentage = weighted_sum / total_weight * 100.0;
+
+    let (letter, grade_points) = GRADING_SCALE.with(|scale| {
+        scale
+            .borrow()
+            .get()
+            .band_for(percentage)
+            .map(|band| (band.letter.clone(), band.grade_points))
+            .unwrap_or_default()
+    });
+
+    Some(CourseAverage {
+        student_id,
+        course_id,
+        percentage,
+        letter,
+        grade_points,
+        assessment_count: records.len() as u32,
+    })
+}
+
+/// Retrieves the grading scale used for letter grades and grade points.
+#[ic_cdk::query]
+fn get_grading_scale() -> Result<GradingScale, Error> {
+    require_member()?;
+    Ok(GRADING_SCALE.with(|scale| scale.borrow().get().clone()))
+}
+
+/// Replaces the grading scale used for letter grades and grade points.
+#[ic_cdk::update]
+fn set_grading_scale(scale: GradingScale) -> Result<GradingScale, Error> {
+    require_admin()?;
+    let existing_scale = GRADING_SCALE.with(|cell| cell.borrow().get().clone());
+    let scale = store_grading_scale(scale)?;
+    audit("set_grading_scale", "GradingScale", None, Some(&existing_scale), Some(&scale));
+    Ok(scale)
+}
+
+/// Validates and stores the grading scale.
+fn store_grading_scale(scale: GradingScale) -> Result<GradingScale, Error> {
+    if !scale.bands.iter().any(|band| band.min_percentage == 0.0) {
+        return Err(Error::invalid_input("bands", "A band starting at 0 percent is required"));
+    }
+    for band in &scale.bands {
+        if !(0.0..=100.0).contains(&band.min_percentage) {
+            return Err(Error::invalid_input("min_percentage", "Band bounds must be between 0 and 100"));
+        }
+        if band.letter.is_empty() {
+            return Err(Error::invalid_input("letter", "Letter is a required field"));
+        }
+        if !band.grade_points.is_finite() || band.grade_points < 0.0 {
+            return Err(Error::invalid_input("grade_points", "Grade points must not be negative"));
+        }
+    }
+    let mut bounds: Vec<f64> = scale.bands.iter().map(|band| band.min_percentage).collect();
+    bounds.sort_by(f64::total_cmp);
+    if bounds.windows(2).any(|pair| pair[0] == pair[1]) {
+        return Err(Error::invalid_input("min_percentage", "Band bounds must be unique"));
+    }
+
+    GRADING_SCALE.with(|cell| {
+        cell.borrow_mut()
+            .set(scale.clone())
+            .map_err(|_| Error::invalid_input("bands", "Grading scale is too large"))
+    })?;
+
+    Ok(scale)
+}
+
+/// Retrieves a student's weighted average in a course.
+#[ic_cdk::query]
+fn get_course_average(student_id: StudentId, course_id: CourseId) -> Result<CourseAverage, Error> {
+    require_student_access(student_id)?;
+    if !STUDENT_STORAGE.with(|storage| storage.borrow().contains_key(&student_id)) {
+        return Err(Error::not_found("Student", student_id));
+    }
+    if !COURSE_STORAGE.with(|storage| storage.borrow().contains_key(&course_id)) {
+        return Err(Error::not_found("Course", course_id));
+    }
+    compute_course_average(student_id, course_id).ok_or_else(|| {
+        Error::invalid_input("course_id", "No grades are recorded for the student in this course")
+    })
+}
+
+/// Retrieves a student's grade point average across their graded, enrolled courses.
+#[ic_cdk::query]
+fn get_gpa(student_id: StudentId) -> Result<Gpa, Error> {
+    require_student_access(student_id)?;
+    let student = STUDENT_STORAGE
+        .with(|storage| storage.borrow().get(&student_id))
+        .ok_or_else(|| Error::not_found("Student", student_id))?;
+
+    let course_averages: Vec<CourseAverage> = student
+        .enrolled_courses
+        .iter()
+        .filter_map(|course_id| compute_course_average(student_id, *course_id))
+        .collect();
+    let gpa = if course_averages.is_empty() {
+        None
+    } else {
+        let total: f64 = course_averages.iter().map(|average| average.grade_points).sum();
+        Some(total / course_averages.len() as f64)
+    };
+
+    Ok(Gpa {
+        student_id,
+        gpa,
+        course_averages,
+    })
+}
+
+/// Collects the schedule slots referenced by an index.
+fn slots_from_index<K: EntityId>(index: &'static LocalKey<RefCell<IdIndex<K>>>, owner_id: K) -> Vec<ScheduleSlot> {
+    let ids = index_ids(index, owner_id);
+    SCHEDULE_SLOT_STORAGE.with(|storage| {
+        let storage = storage.borrow();
+        ids.iter().filter_map(|id| storage.get(id)).collect()
+    })
+}
+
+/// Collects the schedule slots of every course assigned to the teacher.
+fn teacher_slots(teacher: &Teacher) -> Vec<ScheduleSlot> {
+    teacher
+        .assigned_courses
+        .iter()
+        .flat_map(|course_id| slots_from_index(&COURSE_SLOT_INDEX, *course_id))
+        .collect()
+}
+
+/// Stores a teacher with its schedule rebuilt from its assigned courses, auditing the change
+/// from `existing_teacher`.
+fn store_teacher(endpoint: &str, existing_teacher: &Teacher, mut teacher: Teacher) {
+    teacher.schedule = teacher_slots(&teacher).iter().map(|slot| slot.id).collect();
+    teacher.metadata.touch();
+    TEACHER_STORAGE.with(|storage| storage.borrow_mut().insert(teacher.id, teacher.clone()));
+    audit(endpoint, "Teacher", Some(teacher.id.into()), Some(existing_teacher), Some(&teacher));
+}
+
+/// Rebuilds and stores the schedule of the teacher assigned to a course, if any.
+fn refresh_teacher_schedule(endpoint: &str, course: &Course) {
+    let teacher = course
+        .teacher_id
+        .and_then(|teacher_id| TEACHER_STORAGE.with(|storage| storage.borrow().get(&teacher_id)));
+    if let Some(teacher) = teacher.filter(|teacher| teacher.assigned_courses.contains(&course.id)) {
+        store_teacher(endpoint, &teacher.clone(), teacher);
+    }
+}
+
+/// Ensures none of the slots overlap a session the teacher already teaches.
+fn ensure_teacher_available(teacher: &Teacher, slots: &[ScheduleSlot]) -> Result<(), Error> {
+    let taught = teacher_slots(teacher);
+    if taught.len() + slots.len() > MAX_SLOTS_PER_TEACHER {
+        return Err(Error::CapacityExceeded {
+            entity: "Teacher".to_string(),
+            id: teacher.id.into(),
+            capacity: MAX_SLOTS_PER_TEACHER as u32,
+        });
+    }
+    for slot in slots {
+        if let Some(existing) = taught.iter().find(|existing| existing.conflicts_with(slot)) {
+            return Err(Error::conflict(
+                "Teacher",
+                format!(
+                    "Teacher {} already teaches slot {} of course {} at that time",
+                    teacher.id, existing.id, existing.course_id
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn validate_schedule_slot(payload: &ScheduleSlotPayload) -> Result<(), Error> {
+    let mut validator = Validator::new();
+    validator
+        .check("start_time", payload.start_time.is_valid(), "Must be a valid time of day")
+        .check("end_time", payload.end_time.is_valid(), "Must be a valid time of day")
+        .check("end_time", payload.start_time < payload.end_time, "Must be after the start time")
+        .check("effective_from", payload.effective_from.is_valid(), "Must be a valid calendar date");
+    if let Some(until) = payload.effective_until {
+        validator
+            .check("effective_until", until.is_valid(), "Must be a valid calendar date")
+            .check("effective_until", until >= payload.effective_from, "Must not precede the effective start");
+    }
+    if payload.recurrence == Recurrence::Once && payload.effective_from.is_valid() {
+        validator.check(
+            "day_of_week",
+            payload.effective_from.day_of_week() == payload.day_of_week,
+            "A one-off slot must fall on the day of its effective start date",
+        );
+    }
+    validator.finish()
+}
+
+/// Adds a schedule slot to a course's timetable.
+#[ic_cdk::update]
+fn add_schedule_slot(course_id: CourseId, payload: ScheduleSlotPayload) -> Result<ScheduleSlot, Error> {
+    require_registrar()?;
+    validate_schedule_slot(&payload)?;
+    let course = COURSE_STORAGE
+        .with(|storage| storage.borrow().get(&course_id))
+        .ok_or_else(|| Error::not_found("Course", course_id))?;
+    let classroom = CLASSROOM_STORAGE
+        .with(|storage| storage.borrow().get(&payload.classroom_id))
+        .ok_or_else(|| Error::not_found("Classroom", payload.classroom_id))?;
+    if course.student_ids.len() > classroom.capacity as usize {
+        return Err(Error::CapacityExceeded {
+            entity: "Classroom".to_string(),
+            id: classroom.id.into(),
+            capacity: classroom.capacity,
+        });
+    }
+
+    let mut slot = ScheduleSlot {
+        id: 0,
+        course_id,
+        day_of_week: payload.day_of_week,
+        start_time: payload.start_time,
+        end_time: payload.end_time,
+        classroom_id: payload.classroom_id,
+        recurrence: payload.recurrence,
+        effective_from: payload.effective_from,
+        effective_until: payload.effective_until,
+    };
+
+    if let Some(booking) = slots_from_index(&CLASSROOM_SLOT_INDEX, slot.classroom_id)
+        .into_iter()
+        .find(|booking| booking.conflicts_with(&slot))
+    {
+        return Err(Error::conflict(
+            "Classroom",
+            format!(
+                "Classroom {} is already booked by slot {} of course {}",
+                slot.classroom_id, booking.id, booking.course_id
+            ),
+        ));
+    }
+    if let Some(teacher) = course
+        .teacher_id
+        .and_then(|teacher_id| TEACHER_STORAGE.with(|storage| storage.borrow().get(&teacher_id)))
+        .filter(|teacher| teacher.assigned_courses.contains(&course_id))
+    {
+        ensure_teacher_available(&teacher, std::slice::from_ref(&slot))?;
+    }
+
+    let id = next_id(&ID_COUNTER);
+    slot.id = id;
+
+    SCHEDULE_SLOT_STORAGE.with(|storage| storage.borrow_mut().insert(id, slot.clone()));
+    COURSE_SLOT_INDEX.with(|index| index.borrow_mut().insert((course_id, id), ()));
+    CLASSROOM_SLOT_INDEX.with(|index| index.borrow_mut().insert((slot.classroom_id, id), ()));
+    refresh_teacher_schedule("add_schedule_slot", &course);
+    audit("add_schedule_slot", "ScheduleSlot", Some(id), None, Some(&slot));
+
+    Ok(slot)
+}
+
+/// Removes a schedule slot and its index entries.
+fn remove_slot(endpoint: &str, slot: &ScheduleSlot) {
+    SCHEDULE_SLOT_STORAGE.with(|storage| storage.borrow_mut().remove(&slot.id));
+    COURSE_SLOT_INDEX.with(|index| index.borrow_mut().remove(&(slot.course_id, slot.id)));
+    CLASSROOM_SLOT_INDEX.with(|index| index.borrow_mut().remove(&(slot.classroom_id, slot.id)));
+    audit(endpoint, "ScheduleSlot", Some(slot.id), Some(slot), None);
+}
+
+/// Removes a schedule slot from its course's timetable.
+#[ic_cdk::update]
+fn remove_schedule_slot(slot_id: u64) -> Result<(), Error> {
+    require_registrar()?;
+    let slot = SCHEDULE_SLOT_STORAGE
+        .with(|storage| storage.borrow().get(&slot_id))
+        .ok_or_else(|| Error::not_found("ScheduleSlot", slot_id))?;
+    remove_slot("remove_schedule_slot", &slot);
+    if let Some(course) = COURSE_STORAGE.with(|storage| storage.borrow().get(&slot.course_id)) {
+        refresh_teacher_schedule("remove_schedule_slot", &course);
+    }
+
+    Ok(())
+}
+
+/// Retrieves a course's weekly timetable, ordered by day and start time.
+#[ic_cdk::query]
+fn get_course_timetable(course_id: CourseId) -> Result<Vec<ScheduleSlot>, Error> {
+    require_member()?;
+    if !COURSE_STORAGE.with(|storage| storage.borrow().contains_key(&course_id)) {
+        return Err(Error::not_found("Course", course_id));
+    }
+    let mut slots = slots_from_index(&COURSE_SLOT_INDEX, course_id);
+    slots.sort_by_key(|slot| (slot.day_of_week, slot.start_time, slot.id));
+
+    Ok(slots)
+}
+
+/// Ensures the teacher exists and can take on the course, given by its ID or None for a course
+/// not stored yet, and returns the teacher, if any.
+fn check_course_teacher(
+    course_id: Option<CourseId>,
+    teacher_id: Option<TeacherId>,
+) -> Result<Option<Teacher>, Error> {
+    let Some(teacher_id) = teacher_id else {
+        return Ok(None);
+    };
+    let teacher = TEACHER_STORAGE
+        .with(|storage| storage.borrow().get(&teacher_id))
+        .ok_or_else(|| Error::not_found("Teacher", teacher_id))?;
+    if !course_id.is_some_and(|course_id| teacher.assigned_courses.contains(&course_id)) {
+        if teacher.assigned_courses.len() >= MAX_COURSES_PER_TEACHER {
+            return Err(Error::CapacityExceeded {
+                entity: "Teacher".to_string(),
+                id: teacher_id.into(),
+                capacity: MAX_COURSES_PER_TEACHER as u32,
+            });
+        }
+        // A course not stored yet has no sessions to fit into the teacher's schedule.
+        if let Some(course_id) = course_id {
+            ensure_teacher_available(&teacher, &slots_from_index(&COURSE_SLOT_INDEX, course_id))?;
+        }
+    }
+    Ok(Some(teacher))
+}
+
+/// Makes the teacher, checked by `check_course_teacher`, the teacher of the course, moving the
+/// course and its sessions off the previous teacher. The caller stores the course.
+fn set_course_teacher(endpoint: &str, course: &mut Course, teacher: Option<Teacher>) {
+    let teacher_id = teacher.as_ref().map(|teacher| teacher.id);
+    if let Some(previous_id) = course.teacher_id.filter(|id| Some(*id) != teacher_id) {
+        if let Some(mut previous) = TEACHER_STORAGE.with(|storage| storage.borrow().get(&previous_id)) {
+            let existing_previous = previous.clone();
+            previous.assigned_courses.retain(|id| *id != course.id);
+            store_teacher(endpoint, &existing_previous, previous);
+        }
+    }
+
+    if let Some(mut teacher) = teacher {
+        let existing_teacher = teacher.clone();
+        if !teacher.assigned_courses.contains(&course.id) {
+            teacher.assigned_courses.push(course.id);
+        }
+        store_teacher(endpoint, &existing_teacher, teacher);
+    }
+    course.teacher_id = teacher_id;
+}
+
+/// Assigns a teacher to a course, moving the course and its sessions off the previous teacher.
+#[ic_cdk::update]
+fn assign_teacher_to_course(teacher_id: TeacherId, course_id: CourseId) -> Result<Course, Error> {
+    require_registrar()?;
+    let mut course = COURSE_STORAGE
+        .with(|storage| storage.borrow().get(&course_id))
+        .ok_or_else(|| Error::not_found("Course", course_id))?;
+    let teacher = check_course_teacher(Some(course.id), Some(teacher_id))?;
+
+    if course.teacher_id == Some(teacher_id)
+        && teacher.as_ref().is_some_and(|teacher| teacher.assigned_courses.contains(&course_id))
+    {
+        return Err(Error::conflict(
+            "Course",
+            format!("Teacher {} is already assigned to course {}", teacher_id, course_id),
+        ));
+    }
+    let existing_course = course.clone();
+
+    set_course_teacher("assign_teacher_to_course", &mut course, teacher);
+    course.metadata.touch();
+
+    COURSE_STORAGE.with(|storage| storage.borrow_mut().insert(course_id, course.clone()));
+    audit(
+        "assign_teacher_to_course",
+        "Course",
+        Some(course_id.into()),
+        Some(&existing_course),
+        Some(&course),
+    );
+
+    Ok(course)
+}
+
+/// Retrieves the sessions a teacher teaches in the Monday-to-Sunday week containing the date.
+#[ic_cdk::query]
+fn get_teacher_timetable(teacher_id: TeacherId, week: Date) -> Result<Vec<TimetableEntry>, Error> {
+    require_teacher_access(teacher_id)?;
+    if !week.is_valid() {
+        return Err(Error::invalid_input("week", "Week is not a valid calendar date"));
+    }
+    let teacher = TEACHER_STORAGE
+        .with(|storage| storage.borrow().get(&teacher_id))
+        .ok_or_else(|| Error::not_found("Teacher", teacher_id))?;
+
+    Ok(week_timetable(week, &teacher_slots(&teacher)))
+}
+
+/// Returns the sessions of the slots in the Monday-to-Sunday week containing `week`, in order.
+fn week_timetable(week: Date, slots: &[ScheduleSlot]) -> Vec<TimetableEntry> {
+    let monday = week.add_days(-(week.day_of_week() as i64));
+    let mut entries: Vec<TimetableEntry> = (0..7)
+        .map(|offset| monday.add_days(offset))
+        .flat_map(|date| {
+            slots
+                .iter()
+                .filter(move |slot| slot.occurs_on(date))
+                .map(move |slot| TimetableEntry {
+                    date,
+                    slot: slot.clone(),
+                })
+        })
+        .collect();
+    entries.sort_by_key(|entry| (entry.date, entry.slot.start_time, entry.slot.id));
+    entries
+}
+
+/// Lists the free windows of a classroom within the school day, for an inclusive date range.
+#[ic_cdk::query]
+fn get_classroom_availability(classroom_id: ClassroomId, from: Date, to: Date) -> Result<Vec<AvailabilityWindow>, Error> {
+    require_member()?;
+    if !from.is_valid() {
+        return Err(Error::invalid_input("from", "Start is not a valid calendar date"));
+    }
+    if !to.is_valid() {
+        return Err(Error::invalid_input("to", "End is not a valid calendar date"));
+    }
+    if to < from {
+        return Err(Error::invalid_input("to", "End must not precede the start"));
+    }
+    if to.to_days() - from.to_days() >= MAX_AVAILABILITY_DAYS {
+        return Err(Error::invalid_input(
+            "to",
+            &format!("At most {} days can be queried at once", MAX_AVAILABILITY_DAYS),
+        ));
+    }
+    if !CLASSROOM_STORAGE.with(|storage| storage.borrow().contains_key(&classroom_id)) {
+        return Err(Error::not_found("Classroom", classroom_id));
+    }
+
+    let bookings = slots_from_index(&CLASSROOM_SLOT_INDEX, classroom_id);
+    let mut windows = Vec::new();
+    for offset in 0..=(to.to_days() - from.to_days()) {
+        let date = from.add_days(offset);
+        let mut busy: Vec<(TimeOfDay, TimeOfDay)> = bookings
+            .iter()
+            .filter(|slot| slot.occurs_on(date))
+            .map(|slot| (slot.start_time, slot.end_time))
+            .collect();
+        busy.sort();
+
+        let mut free_from = SCHOOL_DAY_START;
+        for (start, end) in busy {
+            if start > free_from {
+                windows.push(AvailabilityWindow {
+                    date,
+                    start_time: free_from,
+                    end_time: start.min(SCHOOL_DAY_END),
+                });
+            }
+            free_from = free_from.max(end);
+            if free_from >= SCHOOL_DAY_END {
+                break;
+            }
+        }
+        if free_from < SCHOOL_DAY_END {
+            windows.push(AvailabilityWindow {
+                date,
+                start_time: free_from,
+                end_time: SCHOOL_DAY_END,
+            });
+        }
+    }
+
+    Ok(windows)
+}
+
+/// Reads one page of a storage map, starting after the request's cursor and skipping records
+/// last modified before `modified_since`.
+fn paginate<K: EntityId, V: BoundedStorable + Clone + HasMetadata>(
+    storage: &'static LocalKey<RefCell<StableBTreeMap<K, V, Memory>>>,
+    request: PageRequest,
+    modified_since: Option<u64>,
+) -> Result<Page<V>, Error> {
+    let limit = request.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return Err(Error::invalid_input(
+            "limit",
+            &format!("Limit must be between 1 and {}", MAX_PAGE_SIZE),
+        ));
+    }
+    let start = request.cursor.map_or(Bound::Unbounded, |cursor| Bound::Excluded(K::from(cursor)));
+
+    let mut entries: Vec<(K, V)> = storage.with(|storage| {
+        storage
+            .borrow()
+            .range((start, Bound::Unbounded))
+            .filter(|(_, value)| modified_since.map_or(true, |since| value.metadata().updated_at >= since))
+            .take(limit as usize + 1)
+            .collect()
+    });
+    let next_cursor = if entries.len() > limit as usize {
+        entries.truncate(limit as usize);
+        entries.last().map(|(id, _)| (*id).into())
+    } else {
+        None
+    };
+
+    Ok(Page {
+        items: entries.into_iter().map(|(_, value)| value).collect(),
+        next_cursor,
+    })
+}
+
+/// Finds the student registered with an email address, ignoring case.
+#[ic_cdk::query]
+fn find_student_by_email(email: String) -> Result<Option<Student>, Error> {
+    require_staff()?;
+    let ids = index_ids_with_key(&STUDENT_EMAIL_INDEX, &IndexKey::email(&email));
+    Ok(STUDENT_STORAGE.with(|storage| ids.first().and_then(|id| storage.borrow().get(id))))
+}
+
+/// Searches students whose name, or any word of it onwards, starts with the prefix.
+#[ic_cdk::query]
+fn search_students_by_name(prefix: String) -> Result<Vec<Student>, Error> {
+    require_staff()?;
+    let prefix = IndexKey::name_prefix(&prefix);
+    if prefix.0.is_empty() {
+        return Err(Error::invalid_input("prefix", "Prefix is a required field"));
+    }
+    let ids = index_ids_with_prefix(&STUDENT_NAME_INDEX, &prefix, MAX_SEARCH_RESULTS);
+    Ok(STUDENT_STORAGE.with(|storage| {
+        let storage = storage.borrow();
+        ids.iter().filter_map(|id| storage.get(id)).collect()
+    }))
+}
+
+/// Finds the teacher registered with an email address, ignoring case.
+#[ic_cdk::query]
+fn find_teacher_by_email(email: String) -> Result<Option<Teacher>, Error> {
+    require_staff()?;
+    let ids = index_ids_with_key(&TEACHER_EMAIL_INDEX, &IndexKey::email(&email));
+    Ok(TEACHER_STORAGE.with(|storage| ids.first().and_then(|id| storage.borrow().get(id))))
+}
+
+/// Searches teachers whose name, or any word of it onwards, starts with the prefix.
+#[ic_cdk::query]
+fn search_teachers_by_name(prefix: String) -> Result<Vec<Teacher>, Error> {
+    require_staff()?;
+    let prefix = IndexKey::name_prefix(&prefix);
+    if prefix.0.is_empty() {
+        return Err(Error::invalid_input("prefix", "Prefix is a required field"));
+    }
+    let ids = index_ids_with_prefix(&TEACHER_NAME_INDEX, &prefix, MAX_SEARCH_RESULTS);
+    Ok(TEACHER_STORAGE.with(|storage| {
+        let storage = storage.borrow();
+        ids.iter().filter_map(|id| storage.get(id)).collect()
+    }))
+}
+
+/// Lists the email addresses registered more than once across students and teachers.
+#[ic_cdk::query]
+fn find_duplicate_emails() -> Result<Vec<DuplicateEmail>, Error> {
+    fn entry(holders: &mut BTreeMap<String, DuplicateEmail>, email: String) -> &mut DuplicateEmail {
+        holders.entry(email.clone()).or_insert_with(|| DuplicateEmail {
+            email,
+            student_ids: Vec::new(),
+            teacher_ids: Vec::new(),
+        })
+    }
+
+    require_registrar()?;
+    let mut holders: BTreeMap<String, DuplicateEmail> = BTreeMap::new();
+    STUDENT_EMAIL_INDEX.with(|index| {
+        for ((key, id), _) in index.borrow().iter() {
+            entry(&mut holders, key.0).student_ids.push(id);
+        }
+    });
+    TEACHER_EMAIL_INDEX.with(|index| {
+        for ((key, id), _) in index.borrow().iter() {
+            entry(&mut holders, key.0).teacher_ids.push(id);
+        }
+    });
+
+    Ok(holders
+        .into_values()
+        .filter(|entry| entry.student_ids.len() + entry.teacher_ids.len() > 1)
+        .collect())
+}
+
+/// Retrieves a student's age in whole years on a date, today by default.
+#[ic_cdk::query]
+fn get_student_age(student_id: StudentId, on: Option<Date>) -> Result<u32, Error> {
+    require_student_access(student_id)?;
+    let student = STUDENT_STORAGE
+        .with(|storage| storage.borrow().get(&student_id))
+        .ok_or_else(|| Error::not_found("Student", student_id))?;
+    let on = on.unwrap_or_else(Date::today);
+    if !on.is_valid() {
+        return Err(Error::invalid_input("on", "Date is not a valid calendar date"));
+    }
+    student
+        .date_of_birth
+        .years_until(on)
+        .ok_or_else(|| Error::invalid_input("on", "Date precedes the student's date of birth"))
+}
+
+/// Retrieves a teacher's completed years of service on a date, today by default.
+#[ic_cdk::query]
+fn get_teacher_years_of_service(teacher_id: TeacherId, on: Option<Date>) -> Result<u32, Error> {
+    require_teacher_access(teacher_id)?;
+    let teacher = TEACHER_STORAGE
+        .with(|storage| storage.borrow().get(&teacher_id))
+        .ok_or_else(|| Error::not_found("Teacher", teacher_id))?;
+    let on = on.unwrap_or_else(Date::today);
+    if !on.is_valid() {
+        return Err(Error::invalid_input("on", "Date is not a valid calendar date"));
+    }
+    teacher
+        .employment_date
+        .years_until(on)
+        .ok_or_else(|| Error::invalid_input("on", "Date precedes the teacher's employment date"))
+}
+
+/// Lists students in ID order, one page at a time, optionally only those modified at or after
+/// `modified_since` (nanoseconds since the UNIX epoch).
+#[ic_cdk::query]
+fn list_students(request: PageRequest, modified_since: Option<u64>) -> Result<Page<Student>, Error> {
+    require_staff()?;
+    paginate(&STUDENT_STORAGE, request, modified_since)
+}
+
+/// Lists teachers in ID order, one page at a time, optionally only those modified at or after
+/// `modified_since` (nanoseconds since the UNIX epoch).
+#[ic_cdk::query]
+fn list_teachers(request: PageRequest, modified_since: Option<u64>) -> Result<Page<Teacher>, Error> {
+    require_staff()?;
+    paginate(&TEACHER_STORAGE, request, modified_since)
+}
+
+/// Lists courses in ID order, one page at a time, optionally only those modified at or after
+/// `modified_since` (nanoseconds since the UNIX epoch).
+#[ic_cdk::query]
+fn list_courses(request: PageRequest, modified_since: Option<u64>) -> Result<Page<Course>, Error> {
+    require_member()?;
+    paginate(&COURSE_STORAGE, request, modified_since)
+}
+
+/// Lists classrooms in ID order, one page at a time, optionally only those modified at or after
+/// `modified_since` (nanoseconds since the UNIX epoch).
+#[ic_cdk::query]
+fn list_classrooms(request: PageRequest, modified_since: Option<u64>) -> Result<Page<Classroom>, Error> {
+    require_member()?;
+    paginate(&CLASSROOM_STORAGE, request, modified_since)
+}
+
+/// Lists archived students in ID order, one page at a time.
+#[ic_cdk::query]
+fn list_archived_students(request: PageRequest) -> Result<Page<Archived<Student>>, Error> {
+    require_registrar()?;
+    paginate(&STUDENT_ARCHIVE, request, None)
+}
+
+/// Lists archived teachers in ID order, one page at a time.
+#[ic_cdk::query]
+fn list_archived_teachers(request: PageRequest) -> Result<Page<Archived<Teacher>>, Error> {
+    require_registrar()?;
+    paginate(&TEACHER_ARCHIVE, request, None)
+}
+
+/// Lists archived courses in ID order, one page at a time.
+#[ic_cdk::query]
+fn list_archived_courses(request: PageRequest) -> Result<Page<Archived<Course>>, Error> {
+    require_registrar()?;
+    paginate(&COURSE_ARCHIVE, request, None)
+}
+
+/// Lists archived classrooms in ID order, one page at a time.
+#[ic_cdk::query]
+fn list_archived_classrooms(request: PageRequest) -> Result<Page<Archived<Classroom>>, Error> {
+    require_registrar()?;
+    paginate(&CLASSROOM_ARCHIVE, request, None)
+}
+
+/// Lists archived guardians in ID order, one page at a time.
+#[ic_cdk::query]
+fn list_archived_guardians(request: PageRequest) -> Result<Page<Archived<Guardian>>, Error> {
+    require_registrar()?;
+    paginate(&GUARDIAN_ARCHIVE, request, None)
+}
+
+/// Returns the guardian the caller signs in as.
+fn caller_guardian() -> Result<Guardian, Error> {
+    let caller = caller();
+    if caller == Principal::anonymous() {
+        return Err(Error::unauthorized("Anonymous callers are not allowed"));
+    }
+    roles_of(caller)
+        .into_iter()
+        .find_map(|role| match role {
+            Role::Guardian { guardian_id } => {
+                GUARDIAN_STORAGE.with(|storage| storage.borrow().get(&guardian_id))
+            }
+            _ => None,
+        })
+        .ok_or_else(|| Error::unauthorized("The caller is not a guardian"))
+}
+
+/// Returns the student if it is linked to the calling guardian.
+fn caller_child(student_id: StudentId) -> Result<Student, Error> {
+    let guardian = caller_guardian()?;
+    if !guardian_student_ids(guardian.id).contains(&student_id) {
+        return Err(Error::unauthorized("The student is not linked to the calling guardian"));
+    }
+    STUDENT_STORAGE
+        .with(|storage| storage.borrow().get(&student_id))
+        .ok_or_else(|| Error::not_found("Student", student_id))
+}
+
+/// Retrieves the students linked to the calling guardian.
+#[ic_cdk::query]
+fn my_children() -> Result<Vec<Student>, Error> {
+    let guardian = caller_guardian()?;
+    let student_ids = guardian_student_ids(guardian.id);
+    Ok(STUDENT_STORAGE.with(|storage| {
+        let storage = storage.borrow();
+        student_ids.iter().filter_map(|id| storage.get(id)).collect()
+    }))
+}
+
+/// Retrieves the attendance of a child of the calling guardian, optionally limited to an
+/// inclusive date range.
+#[ic_cdk::query]
+fn child_attendance(
+    student_id: StudentId,
+    from: Option<Date>,
+    to: Option<Date>,
+) -> Result<Vec<AttendanceRecord>, Error> {
+    caller_child(student_id)?;
+    Ok(attendance_from_index(&STUDENT_ATTENDANCE_INDEX, student_id, from, to))
+}
+
+/// Retrieves the grades of a child of the calling guardian, optionally limited to a single course.
+#[ic_cdk::query]
+fn child_grades(student_id: StudentId, course_id: Option<CourseId>) -> Result<Vec<PerformanceRecord>, Error> {
+    caller_child(student_id)?;
+    Ok(student_grades(student_id, course_id))
+}
+
+/// Retrieves the sessions of the enrolled courses of a child of the calling guardian in the
+/// Monday-to-Sunday week containing `week`.
+#[ic_cdk::query]
+fn child_timetable(student_id: StudentId, week: Date) -> Result<Vec<TimetableEntry>, Error> {
+    let student = caller_child(student_id)?;
+    if !week.is_valid() {
+        return Err(Error::invalid_input("week", "Week is not a valid calendar date"));
+    }
+    let slots: Vec<ScheduleSlot> = student
+        .enrolled_courses
+        .iter()
+        .flat_map(|course_id| slots_from_index(&COURSE_SLOT_INDEX, *course_id))
+        .collect();
+    Ok(week_timetable(week, &slots))
+}
+
+/// Ensures the entity a role refers to exists.
+fn validate_role(role: &Role) -> Result<(), Error> {
+    match *role {
+        Role::Teacher { teacher_id } => {
+            if !TEACHER_STORAGE.with(|storage| storage.borrow().contains_key(&teacher_id)) {
+                return Err(Error::not_found("Teacher", teacher_id));
+            }
+        }
+        Role::Student { student_id } => {
+            if !STUDENT_STORAGE.with(|storage| storage.borrow().contains_key(&student_id)) {
+                return Err(Error::not_found("Student", student_id));
+            }
+        }
+        Role::Guardian { guardian_id } => {
+            if !GUARDIAN_STORAGE.with(|storage| storage.borrow().contains_key(&guardian_id)) {
+                return Err(Error::not_found("Guardian", guardian_id));
+            }
+        }
+        Role::Admin | Role::Registrar => {}
+    }
+    Ok(())
+}
+
+/// Grants a role to a principal and returns the principal's roles.
+#[ic_cdk::update]
+fn grant_role(principal: Principal, role: Role) -> Result<Vec<Role>, Error> {
+    require_admin()?;
+    add_role("grant_role", principal, role)
+}
+
+/// Adds a role to the roles of a principal and returns the principal's roles.
+fn add_role(endpoint: &str, principal: Principal, role: Role) -> Result<Vec<Role>, Error> {
+    if principal == Principal::anonymous() {
+        return Err(Error::invalid_input("principal", "Roles cannot be granted to the anonymous principal"));
+    }
+    validate_role(&role)?;
+
+    let existing_roles = roles_of(principal);
+    if existing_roles.contains(&role) {
+        return Ok(existing_roles);
+    }
+    if existing_roles.len() >= MAX_ROLES_PER_PRINCIPAL {
+        return Err(Error::invalid_input(
+            "role",
+            &format!("A principal can hold at most {} roles", MAX_ROLES_PER_PRINCIPAL),
+        ));
+    }
+    let mut roles = existing_roles.clone();
+    roles.push(role);
+    store_roles(endpoint, principal, &existing_roles, &roles);
+    Ok(roles)
+}
+
+/// Revokes a role from a principal and returns the principal's remaining roles.
+#[ic_cdk::update]
+fn revoke_role(principal: Principal, role: Role) -> Result<Vec<Role>, Error> {
+    require_admin()?;
+    remove_role("revoke_role", principal, role)
+        .ok_or_else(|| Error::invalid_input("role", "The principal does not hold the role"))
+}
+
+/// Removes a role from the roles of a principal and returns the principal's remaining roles, or
+/// `None` if the principal does not hold the role.
+fn remove_role(endpoint: &str, principal: Principal, role: Role) -> Option<Vec<Role>> {
+    let existing_roles = roles_of(principal);
+    if !existing_roles.contains(&role) {
+        return None;
+    }
+    let mut roles = existing_roles.clone();
+    roles.retain(|held| *held != role);
+    store_roles(endpoint, principal, &existing_roles, &roles);
+    Some(roles)
+}
+
+/// Stores the roles of a principal, removing principals left without any role, and appends the
+/// change to the audit log.
+fn store_roles(endpoint: &str, principal: Principal, before: &[Role], after: &[Role]) {
+    ROLE_STORAGE.with(|storage| {
+        let mut storage = storage.borrow_mut();
+        if after.is_empty() {
+            storage.remove(&PrincipalKey(principal));
+        } else {
+            storage.insert(PrincipalKey(principal), RoleSet { roles: after.to_vec() });
+        }
+    });
+    audit(endpoint, "Role", None, Some(&role_snapshot(principal, before)), Some(&role_snapshot(principal, after)));
+}
+
+/// Describes the roles of a principal for the audit log.
+fn role_snapshot<R: serde::Serialize>(principal: Principal, roles: &[R]) -> serde_json::Value {
+    serde_json::json!({ "principal": principal.to_text(), "roles": roles })
+}
+
+/// Retrieves the roles granted to a principal.
+#[ic_cdk::query]
+fn get_roles(principal: Principal) -> Result<Vec<Role>, Error> {
+    require_admin()?;
+    Ok(roles_of(principal))
+}
+
+/// Retrieves the roles granted to the caller.
+#[ic_cdk::query]
+fn get_my_roles() -> Vec<Role> {
+    roles_of(caller())
+}
+
+/// Retrieves the audit entries matching the filter, oldest first.
+///
+/// A page examines at most `MAX_AUDIT_SCAN` entries, so it can hold fewer items than the
+/// limit even when more matching entries follow; continue from `next_cursor` until it is None.
+#[ic_cdk::query]
+fn get_audit_log(filter: AuditFilter, request: PageRequest) -> Result<Page<AuditEntry>, Error> {
+    require_admin()?;
+    let limit = request.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return Err(Error::invalid_input(
+            "limit",
+            &format!("Limit must be between 1 and {}", MAX_PAGE_SIZE),
+        ));
+    }
+
+    AUDIT_LOG.with(|log| {
+        let log = log.borrow();
+        let start = request.cursor.map_or(0, |cursor| cursor + 1);
+        let end = log.len().min(start.saturating_add(MAX_AUDIT_SCAN));
+        let mut items = Vec::new();
+        for id in start..end {
+            let Some(entry) = log.get(id) else { break };
+            if filter.matches(&entry) {
+                items.push(entry);
+                if items.len() == limit as usize {
+                    let next_cursor = (id + 1 < log.len()).then_some(id);
+                    return Ok(Page { items, next_cursor });
+                }
+            }
+        }
+        let next_cursor = (end < log.len()).then(|| end - 1);
+        Ok(Page { items, next_cursor })
+    })
+}
+
+// need this to generate candid
+ic_cdk::export_candid!();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_stable_structures::storable::Blob;
+
+    thread_local! {
+        static CALLER: RefCell<Principal> = RefCell::new(controller());
+        static NOW: RefCell<u64> = const { RefCell::new(1_700_000_000_000_000_000) };
+    }
+
+    /// Returns the principal the tests treat as the controller of the canister.
+    fn controller() -> Principal {
+        Principal::management_canister()
+    }
+
+    /// Makes the principal the caller of subsequent calls on this thread.
+    fn act_as(principal: Principal) {
+        CALLER.with(|caller| *caller.borrow_mut() = principal);
+    }
+
+    /// Stands in for the caller, which only exists inside a canister.
+    pub(super) fn caller() -> Principal {
+        CALLER.with(|caller| *caller.borrow())
+    }
+
+    /// Stands in for the controller check, which only exists inside a canister.
+    pub(super) fn is_controller(principal: &Principal) -> bool {
+        *principal == controller()
+    }
+
+    /// Stands in for the time, which only exists inside a canister.
+    pub(super) fn time() -> u64 {
+        NOW.with(|now| *now.borrow())
+    }
+
+    /// Moves the time of subsequent calls on this thread forward.
+    fn advance_time(nanoseconds: u64) {
+        NOW.with(|now| *now.borrow_mut() += nanoseconds);
+    }
+
+    fn date(year: u16, month: u8, day: u8) -> Date {
+        Date { year, month, day }
+    }
+
+    /// Returns the fields a validation error reports, in order.
+    fn invalid_fields<T>(result: Result<T, Error>) -> Vec<String> {
+        match result {
+            Err(Error::ValidationFailed { errors }) => errors.into_iter().map(|error| error.field).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn new_student(name: &str) -> Student {
+        add_student(StudentPayload {
+            name: name.to_string(),
+            email: format!("{}@example.com", name.to_lowercase().replace(' ', ".")),
+            date_of_birth: date(2016, 1, 1),
+            ..Default::default()
+        })
+        .ok()
+        .unwrap()
+    }
+
+    fn new_course(name: &str) -> Course {
+        add_course(CoursePayload {
+            name: name.to_string(),
+            description: format!("{} course", name),
+            ..Default::default()
+        })
+        .ok()
+        .unwrap()
+    }
+
+    fn new_teacher(name: &str) -> Teacher {
+        add_teacher(TeacherPayload {
+            name: name.to_string(),
+            email: format!("{}@example.com", name.to_lowercase()),
+            employment_date: date(2020, 9, 1),
+            ..Default::default()
+        })
+        .ok()
+        .unwrap()
+    }
+
+    fn grade(student: &Student, course: &Course, assessment_name: &str, score: f64) -> GradePayload {
+        GradePayload {
+            student_id: student.id,
+            course_id: course.id,
+            teacher_id: course.teacher_id.unwrap_or_default(),
+            assessment_name: assessment_name.to_string(),
+            score,
+            max_score: 100.0,
+            weight: 1.0,
+        }
+    }
+
+    #[test]
+    fn reports_missing_records_as_not_found() {
+        assert_eq!(get_student(StudentId::from(7)).err(), Some(Error::not_found("Student", 7u64)));
+        assert_eq!(update_teacher(TeacherId::from(7), TeacherPatch::default()).err(), Some(Error::not_found("Teacher", 7u64)));
+        assert_eq!(delete_course(CourseId::from(7), "Duplicate".to_string(), None).err(), Some(Error::not_found("Course", 7u64)));
+        assert_eq!(
+            delete_classroom(ClassroomId::from(7), "Duplicate".to_string(), None).err(),
+            Some(Error::not_found("Classroom", 7u64))
+        );
+    }
+
+    #[test]
+    fn reports_the_invalid_field() {
+        let payload = StudentPayload {
+            email: "ada@example.com".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(invalid_fields(add_student(payload)), vec!["name", "date_of_birth"]);
+    }
+
+    #[test]
+    fn enrollment_updates_both_sides() {
+        let student = new_student("Ada");
+        let course = new_course("Algebra");
+
+        let enrolled = enroll_student(student.id, course.id).ok().unwrap();
+        assert_eq!(enrolled.student_ids, vec![student.id]);
+        assert_eq!(get_student(student.id).ok().unwrap().enrolled_courses, vec![course.id]);
+        assert!(matches!(enroll_student(student.id, course.id), Err(Error::Conflict { .. })));
+
+        let dropped = drop_student(student.id, course.id).ok().unwrap();
+        assert!(dropped.student_ids.is_empty());
+        assert!(get_student(student.id).ok().unwrap().enrolled_courses.is_empty());
+        assert!(matches!(drop_student(student.id, course.id), Err(Error::Conflict { .. })));
+    }
+
+    #[test]
+    fn dropping_repairs_a_half_written_enrollment() {
+        let student = new_student("Ada");
+        let mut course = new_course("Algebra");
+        course.student_ids.push(student.id);
+        COURSE_STORAGE.with(|storage| storage.borrow_mut().insert(course.id, course.clone()));
+
+        assert!(drop_student(student.id, course.id).ok().unwrap().student_ids.is_empty());
+    }
+
+    #[test]
+    fn enrollment_respects_the_roster_capacity() {
+        let course = new_course("Algebra");
+        for i in 0..MAX_STUDENTS_PER_COURSE {
+            let student = new_student(&format!("Student{}", i));
+            assert!(enroll_student(student.id, course.id).is_ok());
+        }
+
+        let student = new_student("Ada");
+        assert!(matches!(
+            enroll_student(student.id, course.id),
+            Err(Error::CapacityExceeded { capacity: 100, .. })
+        ));
+        assert!(get_student(student.id).ok().unwrap().enrolled_courses.is_empty());
+    }
+
+
+    #[test]
+    fn checks_calendar_dates() {
+        assert!(date(2024, 2, 29).is_valid());
+        assert!(date(2000, 2, 29).is_valid());
+        assert!(!date(1900, 2, 29).is_valid());
+        assert!(!date(2023, 2, 29).is_valid());
+        assert!(!date(2024, 4, 31).is_valid());
+        assert!(!date(2024, 13, 1).is_valid());
+        assert!(!date(0, 1, 1).is_valid());
+
+        let day = date(2025, 9, 15);
+        assert!(day.is_within(None, None));
+        assert!(day.is_within(Some(day), Some(day)));
+        assert!(!day.is_within(Some(date(2025, 9, 16)), None));
+        assert!(!day.is_within(None, Some(date(2025, 9, 14))));
+    }
+
+    fn attendance(student: &Student, course: &Course, session_date: Date) -> AttendancePayload {
+        AttendancePayload {
+            student_id: student.id,
+            course_id: course.id,
+            session_date,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn marks_attendance_of_enrolled_students_once_per_session() {
+        let student = new_student("Ada");
+        let course = new_course("Algebra");
+        assert_eq!(
+            mark_attendance(attendance(&student, &course, date(2025, 9, 1))).err(),
+            Some(Error::invalid_input("student_id", "Student is not enrolled in the course"))
+        );
+
+        enroll_student(student.id, course.id).ok().unwrap();
+        let record = mark_attendance(attendance(&student, &course, date(2025, 9, 1))).ok().unwrap();
+        assert_eq!(record.recorded_by, caller());
+        assert!(matches!(
+            mark_attendance(attendance(&student, &course, date(2025, 9, 1))),
+            Err(Error::Conflict { .. })
+        ));
+        assert!(matches!(
+            mark_attendance(attendance(&student, &course, date(2025, 2, 30))),
+            Err(Error::InvalidInput { .. })
+        ));
+
+        let correction = AttendanceCorrection {
+            status: AttendanceStatus::Excused,
+            note: "Doctor's note".to_string(),
+        };
+        let corrected = correct_attendance(record.id, correction).ok().unwrap();
+        assert_eq!(corrected.status, AttendanceStatus::Excused);
+    }
+
+    #[test]
+    fn looks_up_attendance_by_student_and_course_within_dates() {
+        let student = new_student("Ada");
+        let algebra = new_course("Algebra");
+        let physics = new_course("Physics");
+        for course in [&algebra, &physics] {
+            enroll_student(student.id, course.id).ok().unwrap();
+            for day in [3, 1, 2] {
+                mark_attendance(attendance(&student, course, date(2025, 9, day))).ok().unwrap();
+            }
+        }
+
+        let dates = |records: Vec<AttendanceRecord>| -> Vec<(CourseId, u8)> {
+            records.iter().map(|record| (record.course_id, record.session_date.day)).collect()
+        };
+        let by_course = get_course_attendance(algebra.id, Some(date(2025, 9, 2)), None).ok().unwrap();
+        assert_eq!(dates(by_course), vec![(algebra.id, 2), (algebra.id, 3)]);
+        let by_student = get_student_attendance(student.id, None, Some(date(2025, 9, 1))).ok().unwrap();
+        assert_eq!(dates(by_student), vec![(algebra.id, 1), (physics.id, 1)]);
+    }
+
+    #[test]
+    fn checks_grade_bounds() {
+        assert_eq!(validate_grade(100.0, 100.0, 1.0), Ok(()));
+        assert_eq!(invalid_fields(validate_grade(1.0, 0.0, 1.0)), vec!["max_score", "score"]);
+        assert_eq!(invalid_fields(validate_grade(101.0, 100.0, 1.0)), vec!["score"]);
+        assert_eq!(invalid_fields(validate_grade(f64::NAN, 100.0, 1.0)), vec!["score"]);
+        assert_eq!(invalid_fields(validate_grade(50.0, 100.0, 0.0)), vec!["weight"]);
+    }
+
+    #[test]
+    fn only_the_course_teacher_grades_enrolled_students_once_per_assessment() {
+        let teacher = new_teacher("Grace");
+        let other = new_teacher("Alan");
+        let student = new_student("Ada");
+        let course = add_course(CoursePayload {
+            name: "Algebra".to_string(),
+            description: "Algebra course".to_string(),
+            teacher_id: Some(teacher.id),
+        })
+        .ok()
+        .unwrap();
+
+        assert!(matches!(record_grade(grade(&student, &course, "Quiz", 80.0)), Err(Error::InvalidInput { .. })));
+        enroll_student(student.id, course.id).ok().unwrap();
+        let impostor = GradePayload {
+            teacher_id: other.id,
+            ..grade(&student, &course, "Quiz", 80.0)
+        };
+        assert!(matches!(record_grade(impostor), Err(Error::Unauthorized { .. })));
+
+        let record = record_grade(grade(&student, &course, "Quiz", 80.0)).ok().unwrap();
+        assert_eq!(record.graded_by, teacher.id);
+        assert!(matches!(record_grade(grade(&student, &course, "Quiz", 90.0)), Err(Error::Conflict { .. })));
+
+        let amendment = GradeAmendment {
+            teacher_id: teacher.id,
+            score: 90.0,
+            max_score: 100.0,
+            weight: 2.0,
+        };
+        let amended = amend_grade(record.id, amendment).ok().unwrap();
+        assert_eq!((amended.score, amended.weight), (90.0, 2.0));
+    }
+
+    #[test]
+    fn filters_the_student_gradebook_by_course() {
+        let teacher = new_teacher("Grace");
+        let student = new_student("Ada");
+        let mut courses = Vec::new();
+        for name in ["Algebra", "Physics"] {
+            let course = add_course(CoursePayload {
+                name: name.to_string(),
+                description: format!("{} course", name),
+                teacher_id: Some(teacher.id),
+            })
+            .ok()
+            .unwrap();
+            enroll_student(student.id, course.id).ok().unwrap();
+            record_grade(grade(&student, &course, "Midterm", 70.0)).ok().unwrap();
+            courses.push(course);
+        }
+
+        assert_eq!(get_student_gradebook(student.id, None).ok().unwrap().len(), 2);
+        let algebra = get_student_gradebook(student.id, Some(courses[0].id)).ok().unwrap();
+        assert_eq!(algebra.iter().map(|record| record.course_id).collect::<Vec<_>>(), vec![courses[0].id]);
+        assert_eq!(get_course_gradebook(courses[1].id).ok().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn band_for_includes_the_lower_bound_of_each_band() {
+        let scale = GradingScale::default();
+        let letter = |percentage: f64| scale.band_for(percentage).map(|band| band.letter.as_str());
+
+        assert_eq!(letter(100.0), Some("A"));
+        assert_eq!(letter(90.0), Some("A"));
+        assert_eq!(letter(89.999), Some("B"));
+        assert_eq!(letter(80.0), Some("B"));
+        assert_eq!(letter(60.0), Some("D"));
+        assert_eq!(letter(59.999), Some("F"));
+        assert_eq!(letter(0.0), Some("F"));
+    }
+
+    #[test]
+    fn band_for_ignores_the_order_of_the_bands() {
+        let mut scale = GradingScale::default();
+        scale.bands.reverse();
+
+        assert_eq!(scale.band_for(90.0).map(|band| band.grade_points), Some(4.0));
+        assert_eq!(scale.band_for(75.0).map(|band| band.grade_points), Some(2.0));
+    }
+
+    #[test]
+    fn band_for_returns_none_below_the_lowest_band() {
+        let mut scale = GradingScale::default();
+        scale.bands.retain(|band| band.min_percentage >= 60.0);
+
+        assert!(scale.band_for(59.999).is_none());
+        assert_eq!(scale.band_for(60.0).map(|band| band.letter.as_str()), Some("D"));
+        assert!(GradingScale { bands: vec![] }.band_for(100.0).is_none());
+    }
+
+
+    #[test]
+    fn weights_course_averages_and_averages_grade_points_into_the_gpa() {
+        let teacher = new_teacher("Grace");
+        let student = new_student("Ada");
+        let mut courses = Vec::new();
+        for name in ["Algebra", "Physics", "History"] {
+            let course = add_course(CoursePayload {
+                name: name.to_string(),
+                description: format!("{} course", name),
+                teacher_id: Some(teacher.id),
+            })
+            .ok()
+            .unwrap();
+            enroll_student(student.id, course.id).ok().unwrap();
+            courses.push(course);
+        }
+        record_grade(grade(&student, &courses[0], "Quiz", 60.0)).ok().unwrap();
+        let final_exam = GradePayload {
+            weight: 3.0,
+            ..grade(&student, &courses[0], "Final", 100.0)
+        };
+        record_grade(final_exam).ok().unwrap();
+        record_grade(grade(&student, &courses[1], "Quiz", 85.0)).ok().unwrap();
+
+        let algebra = get_course_average(student.id, courses[0].id).ok().unwrap();
+        assert_eq!((algebra.percentage, algebra.letter.as_str(), algebra.assessment_count), (90.0, "A", 2));
+        assert!(matches!(get_course_average(student.id, courses[2].id), Err(Error::InvalidInput { .. })));
+
+        let gpa = get_gpa(student.id).ok().unwrap();
+        assert_eq!(gpa.gpa, Some(3.5));
+        assert_eq!(gpa.course_averages.len(), 2);
+    }
+
+
+    fn at(hour: u8, minute: u8) -> TimeOfDay {
+        TimeOfDay { hour, minute }
+    }
+
+    fn new_classroom(name: &str) -> Classroom {
+        add_classroom(ClassroomPayload {
+            name: name.to_string(),
+            location: "Main building".to_string(),
+            capacity: 30,
+            ..Default::default()
+        })
+        .ok()
+        .unwrap()
+    }
+
+    fn slot(classroom: &Classroom, day_of_week: DayOfWeek, start_time: TimeOfDay, end_time: TimeOfDay) -> ScheduleSlotPayload {
+        ScheduleSlotPayload {
+            day_of_week,
+            start_time,
+            end_time,
+            classroom_id: classroom.id,
+            effective_from: date(2025, 9, 1),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn finds_the_day_of_the_week() {
+        assert_eq!(date(1970, 1, 1).day_of_week(), DayOfWeek::Thursday);
+        assert_eq!(date(2000, 2, 29).day_of_week(), DayOfWeek::Tuesday);
+        assert_eq!(date(2025, 9, 1).day_of_week(), DayOfWeek::Monday);
+        assert_eq!(date(2025, 9, 1).to_days() - date(2024, 9, 1).to_days(), 365);
+    }
+
+    #[test]
+    fn rejects_malformed_schedule_slots() {
+        let room = new_classroom("Room 1");
+        let field = |payload: ScheduleSlotPayload| invalid_fields(validate_schedule_slot(&payload)).join(", ");
+
+        assert_eq!(field(slot(&room, DayOfWeek::Monday, at(9, 0), at(10, 0))), "");
+        assert_eq!(field(slot(&room, DayOfWeek::Monday, at(24, 0), at(10, 0))), "start_time, end_time");
+        assert_eq!(field(slot(&room, DayOfWeek::Monday, at(10, 0), at(10, 0))), "end_time");
+        let ends_early = ScheduleSlotPayload {
+            effective_until: Some(date(2025, 8, 31)),
+            ..slot(&room, DayOfWeek::Monday, at(9, 0), at(10, 0))
+        };
+        assert_eq!(field(ends_early), "effective_until");
+        let once_on_the_wrong_day = ScheduleSlotPayload {
+            recurrence: Recurrence::Once,
+            ..slot(&room, DayOfWeek::Tuesday, at(9, 0), at(10, 0))
+        };
+        assert_eq!(field(once_on_the_wrong_day), "day_of_week");
+    }
+
+    #[test]
+    fn orders_the_timetable_by_day_and_start_time() {
+        let room = new_classroom("Room 1");
+        let course = new_course("Algebra");
+        let friday = add_schedule_slot(course.id, slot(&room, DayOfWeek::Friday, at(9, 0), at(10, 0))).ok().unwrap();
+        let late_monday = add_schedule_slot(course.id, slot(&room, DayOfWeek::Monday, at(14, 0), at(15, 0))).ok().unwrap();
+        let early_monday = add_schedule_slot(course.id, slot(&room, DayOfWeek::Monday, at(8, 0), at(9, 0))).ok().unwrap();
+
+        let ids = |slots: Vec<ScheduleSlot>| slots.iter().map(|slot| slot.id).collect::<Vec<_>>();
+        assert_eq!(ids(get_course_timetable(course.id).ok().unwrap()), vec![early_monday.id, late_monday.id, friday.id]);
+
+        remove_schedule_slot(late_monday.id).ok().unwrap();
+        assert_eq!(ids(get_course_timetable(course.id).ok().unwrap()), vec![early_monday.id, friday.id]);
+        assert_eq!(remove_schedule_slot(late_monday.id), Err(Error::not_found("ScheduleSlot", late_monday.id)));
+    }
+
+
+    /// Returns a weekly Monday slot from 2025-09-01, a Monday, in classroom 1.
+    fn monday_slot(start_time: TimeOfDay, end_time: TimeOfDay) -> ScheduleSlot {
+        ScheduleSlot {
+            id: 1,
+            course_id: CourseId::from(1),
+            day_of_week: DayOfWeek::Monday,
+            start_time,
+            end_time,
+            classroom_id: ClassroomId::from(1),
+            recurrence: Recurrence::Weekly,
+            effective_from: date(2025, 9, 1),
+            effective_until: None,
+        }
+    }
+
+    #[test]
+    fn overlapping_slots_conflict() {
+        let first = monday_slot(at(9, 0), at(10, 0));
+        let second = monday_slot(at(9, 30), at(10, 30));
+        assert!(first.conflicts_with(&second));
+        assert!(second.conflicts_with(&first));
+        assert!(first.conflicts_with(&monday_slot(at(9, 15), at(9, 45))));
+    }
+
+    #[test]
+    fn adjacent_slots_do_not_conflict() {
+        let first = monday_slot(at(9, 0), at(10, 0));
+        let second = monday_slot(at(10, 0), at(11, 0));
+        assert!(!first.conflicts_with(&second));
+        assert!(!second.conflicts_with(&first));
+    }
+
+    #[test]
+    fn slots_on_different_days_do_not_conflict() {
+        let first = monday_slot(at(9, 0), at(10, 0));
+        let second = ScheduleSlot {
+            day_of_week: DayOfWeek::Tuesday,
+            effective_from: date(2025, 9, 2),
+            ..monday_slot(at(9, 0), at(10, 0))
+        };
+        assert!(!first.conflicts_with(&second));
+    }
+
+    #[test]
+    fn slots_with_disjoint_effective_ranges_do_not_conflict() {
+        let first = ScheduleSlot {
+            effective_until: Some(date(2025, 12, 22)),
+            ..monday_slot(at(9, 0), at(10, 0))
+        };
+        let second = ScheduleSlot {
+            effective_from: date(2026, 1, 5),
+            ..monday_slot(at(9, 0), at(10, 0))
+        };
+        assert!(!first.conflicts_with(&second));
+        assert!(!second.conflicts_with(&first));
+
+        // The ranges share the single Monday 2025-12-22.
+        let touching = ScheduleSlot {
+            effective_from: date(2025, 12, 22),
+            ..monday_slot(at(9, 0), at(10, 0))
+        };
+        assert!(first.conflicts_with(&touching));
+    }
+
+    #[test]
+    fn fortnightly_slots_on_alternate_weeks_do_not_conflict() {
+        let even = ScheduleSlot {
+            recurrence: Recurrence::Fortnightly,
+            ..monday_slot(at(9, 0), at(10, 0))
+        };
+        let odd = ScheduleSlot {
+            effective_from: date(2025, 9, 8),
+            ..even.clone()
+        };
+        assert!(!even.conflicts_with(&odd));
+        assert!(even.conflicts_with(&monday_slot(at(9, 0), at(10, 0))));
+
+        let same_week = ScheduleSlot {
+            effective_from: date(2025, 9, 15),
+            ..even.clone()
+        };
+        assert!(even.conflicts_with(&same_week));
+    }
+
+    #[test]
+    fn one_off_slots_conflict_only_on_their_date() {
+        let weekly = ScheduleSlot {
+            effective_until: Some(date(2025, 9, 29)),
+            ..monday_slot(at(9, 0), at(10, 0))
+        };
+        let inside = ScheduleSlot {
+            recurrence: Recurrence::Once,
+            effective_from: date(2025, 9, 22),
+            ..monday_slot(at(9, 0), at(10, 0))
+        };
+        let after = ScheduleSlot {
+            effective_from: date(2025, 10, 6),
+            ..inside.clone()
+        };
+        assert!(weekly.conflicts_with(&inside));
+        assert!(!weekly.conflicts_with(&after));
+    }
+
+    #[test]
+    fn rejects_double_bookings_and_reports_the_free_windows() {
+        let room = new_classroom("Room 1");
+        let algebra = new_course("Algebra");
+        let physics = new_course("Physics");
+        add_schedule_slot(algebra.id, slot(&room, DayOfWeek::Monday, at(9, 0), at(10, 0))).ok().unwrap();
+
+        let clash = add_schedule_slot(physics.id, slot(&room, DayOfWeek::Monday, at(9, 30), at(10, 30)));
+        assert!(matches!(clash, Err(Error::Conflict { .. })));
+        add_schedule_slot(physics.id, slot(&room, DayOfWeek::Monday, at(10, 0), at(11, 0))).ok().unwrap();
+
+        let windows = get_classroom_availability(room.id, date(2025, 9, 1), date(2025, 9, 1)).ok().unwrap();
+        let times: Vec<_> = windows.iter().map(|window| (window.start_time, window.end_time)).collect();
+        assert_eq!(times, vec![(SCHOOL_DAY_START, at(9, 0)), (at(11, 0), SCHOOL_DAY_END)]);
+        let too_long = get_classroom_availability(room.id, date(2025, 9, 1), date(2025, 10, 2));
+        assert!(matches!(too_long, Err(Error::InvalidInput { .. })));
+    }
+
+
+    #[test]
+    fn derives_the_teacher_schedule_and_rejects_overlapping_courses() {
+        let grace = new_teacher("Grace");
+        let alan = new_teacher("Alan");
+        let (room_1, room_2) = (new_classroom("Room 1"), new_classroom("Room 2"));
+        let (algebra, physics) = (new_course("Algebra"), new_course("Physics"));
+        let lecture = add_schedule_slot(algebra.id, slot(&room_1, DayOfWeek::Monday, at(9, 0), at(10, 0))).ok().unwrap();
+        add_schedule_slot(physics.id, slot(&room_2, DayOfWeek::Monday, at(9, 30), at(10, 30))).ok().unwrap();
+
+        assign_teacher_to_course(grace.id, algebra.id).ok().unwrap();
+        assert_eq!(get_teacher(grace.id).ok().unwrap().schedule, vec![lecture.id]);
+        assert!(matches!(assign_teacher_to_course(grace.id, physics.id), Err(Error::Conflict { .. })));
+        assert!(matches!(assign_teacher_to_course(grace.id, algebra.id), Err(Error::Conflict { .. })));
+
+        let lab = add_schedule_slot(algebra.id, slot(&room_1, DayOfWeek::Wednesday, at(9, 0), at(10, 0))).ok().unwrap();
+        assert_eq!(get_teacher(grace.id).ok().unwrap().schedule, vec![lecture.id, lab.id]);
+        let timetable = get_teacher_timetable(grace.id, date(2025, 9, 3)).ok().unwrap();
+        let sessions: Vec<_> = timetable.iter().map(|entry| (entry.date, entry.slot.id)).collect();
+        assert_eq!(sessions, vec![(date(2025, 9, 1), lecture.id), (date(2025, 9, 3), lab.id)]);
+
+        assign_teacher_to_course(alan.id, algebra.id).ok().unwrap();
+        let grace = get_teacher(grace.id).ok().unwrap();
+        assert!(grace.assigned_courses.is_empty() && grace.schedule.is_empty());
+        assert_eq!(get_teacher(alan.id).ok().unwrap().schedule, vec![lecture.id, lab.id]);
+    }
+
+
+    #[test]
+    fn pages_through_every_record_exactly_once() {
+        let ids: Vec<StudentId> = (0..5).map(|n| new_student(&format!("Student{}", n)).id).collect();
+
+        let first = paginate(&STUDENT_STORAGE, PageRequest { cursor: None, limit: Some(2) }, None).ok().unwrap();
+        assert_eq!(first.items.iter().map(|student| student.id).collect::<Vec<_>>(), ids[..2]);
+        assert_eq!(first.next_cursor, Some(u64::from(ids[1])));
+        let second = paginate(&STUDENT_STORAGE, PageRequest { cursor: first.next_cursor, limit: Some(3) }, None).ok().unwrap();
+        assert_eq!(second.items.iter().map(|student| student.id).collect::<Vec<_>>(), ids[2..]);
+        assert_eq!(second.next_cursor, None);
+
+        let past_the_end = paginate(&STUDENT_STORAGE, PageRequest { cursor: Some(ids[4].into()), limit: None }, None).ok().unwrap();
+        assert!(past_the_end.items.is_empty() && past_the_end.next_cursor.is_none());
+        assert_eq!(list_students(PageRequest::default(), None).ok().unwrap().items.len(), 5);
+    }
+
+    #[test]
+    fn rejects_page_limits_outside_the_bounds() {
+        for limit in [0, MAX_PAGE_SIZE + 1] {
+            let request = PageRequest { cursor: None, limit: Some(limit) };
+            assert!(matches!(list_courses(request, None), Err(Error::InvalidInput { field, .. }) if field == "limit"));
+        }
+        assert!(list_courses(PageRequest { cursor: None, limit: Some(MAX_PAGE_SIZE) }, None).is_ok());
+    }
+
+
+
+    #[test]
+    fn index_keys_normalize_names_and_emails() {
+        assert_eq!(IndexKey::email("  Ada@Example.COM "), IndexKey("ada@example.com".to_string()));
+        assert_eq!(
+            IndexKey::name("Ada  Byron Lovelace"),
+            vec![
+                IndexKey("ada byron lovelace".to_string()),
+                IndexKey("byron lovelace".to_string()),
+                IndexKey("lovelace".to_string()),
+            ]
+        );
+        assert_eq!(IndexKey::name_prefix(" ADA  by"), IndexKey("ada by".to_string()));
+    }
+
+    #[test]
+    fn index_keys_are_truncated_on_a_char_boundary() {
+        let key = IndexKey::new(&"é".repeat(200));
+        assert_eq!(key.0.len(), 256);
+        assert_eq!(IndexKey::new(&format!("a{}", "é".repeat(200))).0.len(), 255);
+    }
+
+    #[test]
+    fn composite_keys_order_by_key_then_id() {
+        let key = |key: &str, id: u64| (IndexKey(key.to_string()), StudentId::from(id));
+        assert!(key("ad", 9) < key("ada", 1));
+        assert!(key("ada", 1) < key("ada", 2));
+        assert!(key("ada", u64::MAX) < key("ada lovelace", 0));
+        assert!(key("ada lovelace", 0) < key("adam", 0));
+    }
+
+    fn index_names(names: &[(u64, &str)]) {
+        for (id, name) in names {
+            insert_index_keys(&STUDENT_NAME_INDEX, IndexKey::name(name), StudentId::from(*id));
+        }
+    }
+
+    fn ids(ids: Vec<StudentId>) -> Vec<u64> {
+        ids.into_iter().map(u64::from).collect()
+    }
+
+    #[test]
+    fn prefix_scans_return_ids_of_matching_keys_only() {
+        index_names(&[(1, "Ada Lovelace"), (2, "Adam Smith"), (3, "Alan Turing"), (4, "Grace Adams")]);
+
+        let search = |prefix: &str| {
+            ids(index_ids_with_prefix(&STUDENT_NAME_INDEX, &IndexKey::name_prefix(prefix), 10))
+        };
+        assert_eq!(search("ada"), vec![1, 2, 4]);
+        assert_eq!(search("ada l"), vec![1]);
+        assert_eq!(search("adams"), vec![4]);
+        assert_eq!(search("al"), vec![3]);
+        assert_eq!(search("b"), Vec::<u64>::new());
+        assert_eq!(search("zz"), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn prefix_scans_skip_duplicate_ids_and_stop_at_the_limit() {
+        index_names(&[(1, "Ada Adams"), (2, "Ada Ada"), (3, "Adam Ade")]);
+
+        let prefix = IndexKey::name_prefix("ad");
+        assert_eq!(ids(index_ids_with_prefix(&STUDENT_NAME_INDEX, &prefix, 10)), vec![2, 1, 3]);
+        assert_eq!(ids(index_ids_with_prefix(&STUDENT_NAME_INDEX, &prefix, 2)), vec![2, 1]);
+    }
+
+    #[test]
+    fn exact_key_scans_ignore_longer_keys() {
+        for (id, email) in [(1, "ada@example.com"), (2, "ada@example.com.au"), (3, "ada@example.com")] {
+            insert_index_keys(&STUDENT_EMAIL_INDEX, vec![IndexKey::email(email)], StudentId::from(id));
+        }
+
+        let holders = index_ids_with_key(&STUDENT_EMAIL_INDEX, &IndexKey::email("ADA@example.com"));
+        assert_eq!(ids(holders), vec![1, 3]);
+
+        remove_index_keys(&STUDENT_EMAIL_INDEX, vec![IndexKey::email("ada@example.com")], StudentId::from(1));
+        let holders = index_ids_with_key(&STUDENT_EMAIL_INDEX, &IndexKey::email("ada@example.com"));
+        assert_eq!(ids(holders), vec![3]);
+    }
+
+    #[test]
+    fn lookups_follow_students_through_updates() {
+        let student = new_student("Ada Lovelace");
+        assert_eq!(find_student_by_email("ADA.LOVELACE@example.com".to_string()).ok().flatten().map(|found| found.id), Some(student.id));
+
+        let renamed = StudentPatch {
+            name: Some("Ada King".to_string()),
+            email: Some("ada@example.com".to_string()),
+            ..Default::default()
+        };
+        update_student(student.id, renamed).ok().unwrap();
+        assert!(find_student_by_email("ada.lovelace@example.com".to_string()).ok().flatten().is_none());
+        assert_eq!(find_student_by_email("Ada@Example.com".to_string()).ok().flatten().map(|found| found.id), Some(student.id));
+        assert!(search_students_by_name("love".to_string()).ok().unwrap().is_empty());
+        assert_eq!(search_students_by_name("kin".to_string()).ok().unwrap().len(), 1);
+        assert!(matches!(search_students_by_name("  ".to_string()), Err(Error::InvalidInput { .. })));
+
+        delete_student(student.id, "Left the school".to_string(), None).ok().unwrap();
+        assert!(find_student_by_email("ada@example.com".to_string()).ok().flatten().is_none());
+    }
+
+
+    #[test]
+    fn emails_are_unique_across_students_and_teachers() {
+        let student = new_student("Ada");
+        let teacher = new_teacher("Grace");
+
+        assert_eq!(ensure_email_available("ADA@example.com", Some(("Student", student.id.into()))), Ok(()));
+        assert_eq!(
+            ensure_email_available(" Ada@Example.com", Some(("Teacher", teacher.id.into()))),
+            Err(Error::EmailInUse {
+                email: "ada@example.com".to_string(),
+                entity: "Student".to_string(),
+                id: student.id.into(),
+            })
+        );
+
+        let clash = TeacherPayload {
+            name: "Ada".to_string(),
+            email: "ada@EXAMPLE.com".to_string(),
+            employment_date: date(2020, 9, 1),
+            ..Default::default()
+        };
+        assert!(matches!(add_teacher(clash), Err(Error::EmailInUse { .. })));
+        let takes_the_teacher_email = StudentPatch {
+            name: Some("Ada".to_string()),
+            email: Some("grace@example.com".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(update_student(student.id, takes_the_teacher_email), Err(Error::EmailInUse { .. })));
+        let keeps_own_email = StudentPatch {
+            name: Some("Ada Lovelace".to_string()),
+            email: Some("Ada@example.com".to_string()),
+            ..Default::default()
+        };
+        assert!(update_student(student.id, keeps_own_email).is_ok());
+    }
+
+    #[test]
+    fn reports_emails_registered_before_uniqueness_was_enforced() {
+        let student = new_student("Ada");
+        let teacher = new_teacher("Grace");
+        insert_index_keys(&TEACHER_EMAIL_INDEX, vec![IndexKey::email("ada@example.com")], teacher.id);
+
+        let duplicates = find_duplicate_emails().ok().unwrap();
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].email, "ada@example.com");
+        assert_eq!((duplicates[0].student_ids.clone(), duplicates[0].teacher_ids.clone()), (vec![student.id], vec![teacher.id]));
+    }
+
+
+    #[test]
+    fn patches_change_only_the_provided_fields() {
+        let mut payload = StudentPayload {
+            name: "Ada".to_string(),
+            grade_level: 9,
+            email: "ada@example.com".to_string(),
+            ..Default::default()
+        };
+        StudentPatch::default().apply_to(&mut payload);
+        assert_eq!((payload.name.as_str(), payload.grade_level), ("Ada", 9));
+        StudentPatch {
+            grade_level: Some(10),
+            address: Some(String::new()),
+            ..Default::default()
+        }
+        .apply_to(&mut payload);
+        assert_eq!((payload.name.as_str(), payload.grade_level, payload.address.as_str()), ("Ada", 10, ""));
+
+        let mut classroom = ClassroomPayload {
+            name: "Room 1".to_string(),
+            capacity: 30,
+            ..Default::default()
+        };
+        ClassroomPatch {
+            capacity: Some(0),
+            ..Default::default()
+        }
+        .apply_to(&mut classroom);
+        assert_eq!((classroom.name.as_str(), classroom.capacity), ("Room 1", 0));
+    }
+
+    #[test]
+    fn updates_validate_the_patched_record() {
+        let student = new_student("Ada");
+        let patch = StudentPatch {
+            grade_level: Some(2),
+            ..Default::default()
+        };
+        let updated = update_student(student.id, patch).ok().unwrap();
+        assert_eq!((updated.name.as_str(), updated.email.as_str(), updated.grade_level), ("Ada", "ada@example.com", 2));
+
+        let clears_the_name = StudentPatch {
+            name: Some(String::new()),
+            ..Default::default()
+        };
+        assert_eq!(invalid_fields(update_student(student.id, clears_the_name)), vec!["name"]);
+        assert_eq!(get_student(student.id).ok().unwrap().grade_level, 2);
+    }
+
+
+    #[test]
+    fn leap_years_follow_the_gregorian_rules() {
+        assert!(Date::is_leap_year(2024));
+        assert!(Date::is_leap_year(2000));
+        assert!(!Date::is_leap_year(1900));
+        assert!(!Date::is_leap_year(2023));
+    }
+
+    #[test]
+    fn validates_month_lengths() {
+        assert!(date(2024, 2, 29).is_valid());
+        assert!(!date(2023, 2, 29).is_valid());
+        assert!(!date(1900, 2, 29).is_valid());
+        assert!(date(2025, 1, 31).is_valid());
+        assert!(!date(2025, 4, 31).is_valid());
+        assert!(date(2025, 12, 31).is_valid());
+        assert!(!date(2025, 13, 1).is_valid());
+        assert!(!date(2025, 0, 1).is_valid());
+        assert!(!date(2025, 1, 0).is_valid());
+        assert!(!date(0, 1, 1).is_valid());
+        assert!(!Date::default().is_valid());
+    }
+
+    #[test]
+    fn converts_dates_to_and_from_days() {
+        assert_eq!(date(1970, 1, 1).to_days(), 0);
+        assert_eq!(date(1969, 12, 31).to_days(), -1);
+        assert_eq!(date(2000, 3, 1).to_days(), 11_017);
+        for days in [-719_162, -1, 0, 59, 365, 11_016, 11_017, 19_782, 2_932_896] {
+            assert_eq!(Date::from_days(days).to_days(), days);
+        }
+        assert_eq!(Date::from_days(-719_162), date(1, 1, 1));
+        assert_eq!(Date::from_days(2_932_896), date(9999, 12, 31));
+    }
+
+    #[test]
+    fn adds_days_across_month_and_year_ends() {
+        assert_eq!(date(2024, 2, 28).add_days(1), date(2024, 2, 29));
+        assert_eq!(date(2023, 2, 28).add_days(1), date(2023, 3, 1));
+        assert_eq!(date(2024, 12, 31).add_days(1), date(2025, 1, 1));
+        assert_eq!(date(2025, 1, 1).add_days(-1), date(2024, 12, 31));
+        assert_eq!(date(2024, 1, 1).add_days(366), date(2025, 1, 1));
+    }
+
+    #[test]
+    fn computes_the_day_of_the_week() {
+        assert_eq!(date(1970, 1, 1).day_of_week(), DayOfWeek::Thursday);
+        assert_eq!(date(2025, 9, 1).day_of_week(), DayOfWeek::Monday);
+        assert_eq!(date(2024, 2, 29).day_of_week(), DayOfWeek::Thursday);
+        assert_eq!(date(2000, 1, 1).day_of_week(), DayOfWeek::Saturday);
+    }
+
+    #[test]
+    fn orders_dates_chronologically() {
+        assert!(date(2024, 12, 31) < date(2025, 1, 1));
+        assert!(date(2025, 1, 31) < date(2025, 2, 1));
+        assert!(date(2025, 2, 1) < date(2025, 2, 2));
+        assert!(date(2025, 3, 1).is_within(Some(date(2025, 3, 1)), Some(date(2025, 3, 1))));
+        assert!(!date(2025, 3, 2).is_within(None, Some(date(2025, 3, 1))));
+        assert!(date(2025, 3, 2).is_within(Some(date(2025, 3, 1)), None));
+    }
+
+    #[test]
+    fn counts_whole_years_between_dates() {
+        let birth = date(2012, 2, 29);
+        assert_eq!(birth.years_until(date(2012, 2, 29)), Some(0));
+        assert_eq!(birth.years_until(date(2013, 2, 28)), Some(0));
+        assert_eq!(birth.years_until(date(2013, 3, 1)), Some(1));
+        assert_eq!(birth.years_until(date(2016, 2, 29)), Some(4));
+        assert_eq!(birth.years_until(date(2012, 2, 28)), None);
+    }
+
+
+    #[test]
+    fn rejects_future_and_implausible_dates() {
+        let student = new_student("Ada");
+        let patch = |grade_level: u8, date_of_birth: Date| StudentPatch {
+            grade_level: Some(grade_level),
+            date_of_birth: Some(date_of_birth),
+            ..Default::default()
+        };
+        let field = |result: Result<Student, Error>| invalid_fields(result).join(", ");
+
+        assert_eq!(field(update_student(student.id, patch(0, date(2023, 2, 29)))), "date_of_birth");
+        assert_eq!(field(update_student(student.id, patch(0, Date::today().add_days(1)))), "date_of_birth");
+        assert_eq!(field(update_student(student.id, patch(9, date(2016, 1, 1)))), "date_of_birth");
+        assert_eq!(field(update_student(student.id, patch(4, date(2016, 1, 1)))), "");
+
+        assert_eq!(get_student_age(student.id, Some(date(2025, 12, 31))).ok(), Some(9));
+        assert!(matches!(get_student_age(student.id, Some(date(2015, 1, 1))), Err(Error::InvalidInput { .. })));
+        let teacher = new_teacher("Grace");
+        assert_eq!(get_teacher_years_of_service(teacher.id, Some(date(2025, 8, 31))).ok(), Some(4));
+    }
+
+
+    #[test]
+    fn candid_file_matches_the_exported_interface() {
+        // The exported interface spells the entity IDs as nat64, which the candid file names
+        // with one alias per kind. Comments in the candid file are not part of the interface.
+        let mut expected: String = include_str!("../school_management_backend.did")
+            .lines()
+            .filter(|line| !line.trim_start().starts_with("//"))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        for id in ["StudentId", "TeacherId", "CourseId", "ClassroomId", "GuardianId"] {
+            let alias = format!("type {} = nat64;\n", id);
+            assert!(expected.contains(&alias), "{} is not declared", id);
+            expected = expected.replace(&alias, "").replace(id, "nat64");
+        }
+        assert_eq!(expected.trim_end(), __export_service().trim_end());
+    }
+
+
+    #[test]
+    fn numbers_each_entity_kind_separately() {
+        assert_eq!(new_student("Ada").id, StudentId::from(1));
+        assert_eq!(new_teacher("Grace").id, TeacherId::from(1));
+        assert_eq!(new_course("Algebra").id, CourseId::from(1));
+        assert_eq!(new_student("Alan").id, StudentId::from(2));
+    }
+
+
+    /// Returns a principal that is not a controller, distinct for each number.
+    fn user(number: u8) -> Principal {
+        Principal::from_slice(&[number])
+    }
+
+    #[test]
+    fn authorizes_admins_and_controllers_and_rejects_the_anonymous_caller() {
+        grant_role(user(1), Role::Admin).ok().unwrap();
+
+        act_as(Principal::anonymous());
+        assert!(matches!(require_member(), Err(Error::Unauthorized { .. })));
+        act_as(user(2));
+        assert!(matches!(require_member(), Err(Error::Unauthorized { .. })));
+        act_as(user(1));
+        assert_eq!(require_admin(), Ok(user(1)));
+        assert_eq!(require_teacher(TeacherId::from(9)), Ok(user(1)));
+        act_as(controller());
+        assert_eq!(require_admin(), Ok(controller()));
+    }
+
+    #[test]
+    fn limits_each_role_to_its_own_records() {
+        let student = new_student("Ada");
+        let other_student = new_student("Alan");
+        let teacher = new_teacher("Grace");
+        let course = add_course(CoursePayload {
+            name: "Algebra".to_string(),
+            description: "Algebra course".to_string(),
+            teacher_id: Some(teacher.id),
+        })
+        .ok()
+        .unwrap();
+        let other_course = new_course("Physics");
+        grant_role(user(1), Role::Registrar).ok().unwrap();
+        grant_role(user(2), Role::Teacher { teacher_id: teacher.id }).ok().unwrap();
+        grant_role(user(3), Role::Student { student_id: student.id }).ok().unwrap();
+
+        act_as(user(1));
+        assert!(get_student(other_student.id).is_ok());
+        assert!(require_course_access(other_course.id).is_ok());
+        assert!(matches!(grant_role(user(4), Role::Admin), Err(Error::Unauthorized { .. })));
+
+        act_as(user(2));
+        assert!(require_course_access(course.id).is_ok());
+        assert!(matches!(require_course_access(other_course.id), Err(Error::Unauthorized { .. })));
+        assert!(require_teacher_access(teacher.id).is_ok());
+        assert!(matches!(require_registrar(), Err(Error::Unauthorized { .. })));
+
+        act_as(user(3));
+        assert!(get_student(student.id).is_ok());
+        assert!(matches!(get_student(other_student.id), Err(Error::Unauthorized { .. })));
+        assert!(matches!(require_staff(), Err(Error::Unauthorized { .. })));
+        assert!(matches!(correct_attendance(99, AttendanceCorrection::default()), Err(Error::Unauthorized { .. })));
+        assert!(get_grading_scale().is_ok());
+        assert_eq!(get_my_roles(), vec![Role::Student { student_id: student.id }]);
+    }
+
+    #[test]
+    fn grants_and_revokes_roles() {
+        let teacher = new_teacher("Grace");
+        assert_eq!(
+            grant_role(user(1), Role::Teacher { teacher_id: TeacherId::from(9) }),
+            Err(Error::not_found("Teacher", TeacherId::from(9)))
+        );
+        assert!(matches!(grant_role(Principal::anonymous(), Role::Admin), Err(Error::InvalidInput { .. })));
+
+        grant_role(user(1), Role::Registrar).ok().unwrap();
+        let roles = grant_role(user(1), Role::Teacher { teacher_id: teacher.id }).ok().unwrap();
+        assert_eq!(roles, vec![Role::Registrar, Role::Teacher { teacher_id: teacher.id }]);
+        assert_eq!(grant_role(user(1), Role::Registrar).ok().unwrap().len(), 2);
+
+        assert_eq!(revoke_role(user(1), Role::Registrar), Ok(vec![Role::Teacher { teacher_id: teacher.id }]));
+        assert!(matches!(revoke_role(user(1), Role::Registrar), Err(Error::InvalidInput { .. })));
+        assert_eq!(revoke_role(user(1), Role::Teacher { teacher_id: teacher.id }), Ok(vec![]));
+        assert!(ROLE_STORAGE.with(|storage| storage.borrow().is_empty()));
+    }
+
+
+    fn init_args(admins: Vec<Principal>) -> InitArgs {
+        InitArgs {
+            admins,
+            school_name: "Hilltop School".to_string(),
+            timezone: "Europe/Berlin".to_string(),
+            academic_year: AcademicYear {
+                start: date(2025, 9, 1),
+                end: date(2026, 7, 31),
+            },
+            grading_scale: None,
+        }
+    }
+
+    #[test]
+    fn init_args_configure_the_school_and_grant_the_first_admins() {
+        apply_init_args("init", init_args(vec![user(1), user(2)])).ok().unwrap();
+        act_as(user(2));
+        assert_eq!(require_admin(), Ok(user(2)));
+
+        let config = get_school_config().ok().unwrap();
+        assert_eq!((config.school_name.as_str(), config.timezone.as_str()), ("Hilltop School", "Europe/Berlin"));
+        assert_eq!(config.academic_year.end, date(2026, 7, 31));
+        assert_eq!(config.grading_scale.bands.len(), GradingScale::default().bands.len());
+    }
+
+    #[test]
+    fn upgrade_args_keep_the_grading_scale_unless_given() {
+        let scale = GradingScale {
+            bands: vec![GradeBand {
+                min_percentage: 0.0,
+                letter: "P".to_string(),
+                grade_points: 1.0,
+            }],
+        };
+        apply_init_args("init", InitArgs {
+            grading_scale: Some(scale),
+            ..init_args(vec![])
+        })
+        .ok()
+        .unwrap();
+        apply_init_args("init", init_args(vec![])).ok().unwrap();
+        assert_eq!(get_school_config().ok().unwrap().grading_scale.bands[0].letter, "P");
+    }
+
+    #[test]
+    fn rejects_invalid_init_args_without_applying_them() {
+        let args = InitArgs {
+            school_name: " ".to_string(),
+            academic_year: AcademicYear {
+                start: date(2026, 7, 31),
+                end: date(2025, 9, 1),
+            },
+            ..init_args(vec![user(1)])
+        };
+        assert_eq!(invalid_fields(apply_init_args("init", args)), vec!["school_name", "academic_year.end"]);
+        assert!(ROLE_STORAGE.with(|storage| storage.borrow().is_empty()));
+        assert!(get_school_config().ok().unwrap().school_name.is_empty());
+    }
+
+
+    #[test]
+    fn parses_only_valid_dates() {
+        assert_eq!(Date::parse("2024-02-29"), Some(date(2024, 2, 29)));
+        assert_eq!(Date::parse("2024-2-9"), Some(date(2024, 2, 9)));
+        assert_eq!(Date::parse("2023-02-29"), None);
+        assert_eq!(Date::parse("2024-02"), None);
+        assert_eq!(Date::parse("2024-02-10-01"), None);
+        assert_eq!(Date::parse("29/02/2024"), None);
+        assert_eq!(Date::parse("70000-01-01"), None);
+    }
+
+    #[test]
+    fn legacy_guardians_take_contact_details_from_the_text() {
+        let guardian = legacy_guardian(
+            GuardianId::from(5),
+            "Grace Hopper (mother)\nTel. +1 555-0100, grace@example.com",
+            Metadata::default(),
+        );
+        assert_eq!(guardian.name, "Grace Hopper (mother)");
+        assert_eq!(guardian.phone, "+1 555-0100");
+        assert_eq!(guardian.email, "grace@example.com");
+        assert_eq!(guardian.notes, "Grace Hopper (mother)\nTel. +1 555-0100, grace@example.com");
+        assert!(validate_guardian_payload(&GuardianPayload::from(&guardian), true).is_ok());
+
+        // The digits of the email address are not taken for a phone number.
+        let guardian = legacy_guardian(GuardianId::from(5), "Grace, 5550100@example.com", Metadata::default());
+        assert_eq!(guardian.email, "5550100@example.com");
+        assert_eq!(guardian.phone, "");
+    }
+
+    #[test]
+    fn legacy_guardians_fit_the_limits_of_their_fields() {
+        let details = format!("{}\n{}", "é".repeat(100), "ü".repeat(400));
+        let guardian = legacy_guardian(GuardianId::from(5), &details, Metadata::default());
+        assert_eq!(guardian.name, "é".repeat(64));
+        assert_eq!(guardian.notes.len(), MAX_LONG_TEXT_LENGTH - 1);
+        assert!(details.starts_with(&guardian.notes));
+        assert!(guardian.to_bytes().len() <= Guardian::MAX_SIZE as usize);
+
+        // Without any contact details found, the guardian can still be updated.
+        let payload = GuardianPayload::from(&guardian);
+        assert!(validate_guardian_payload(&payload, false).is_ok());
+        assert!(validate_guardian_payload(&payload, true).is_err());
+    }
+
+    #[test]
+    fn guardian_patches_can_remove_the_principal() {
+        let mut payload = GuardianPayload {
+            principal: Some(Principal::management_canister()),
+            ..Default::default()
+        };
+        GuardianPatch::default().apply_to(&mut payload);
+        assert_eq!(payload.principal, Some(Principal::management_canister()));
+
+        let patch = GuardianPatch {
+            principal: Some(None),
+            ..Default::default()
+        };
+        patch.apply_to(&mut payload);
+        assert_eq!(payload.principal, None);
+    }
+
+    fn new_guardian(name: &str, principal: Option<Principal>) -> Guardian {
+        add_guardian(GuardianPayload {
+            name: name.to_string(),
+            email: format!("{}@example.com", name.to_lowercase()),
+            principal,
+            ..Default::default()
+        })
+        .ok()
+        .unwrap()
+    }
+
+    #[test]
+    fn links_guardians_and_moves_their_role_with_the_principal() {
+        let student = new_student("Ada");
+        let guardian = new_guardian("Grace", Some(user(1)));
+        assert_eq!(roles_of(user(1)), vec![Role::Guardian { guardian_id: guardian.id }]);
+        assert!(matches!(add_guardian(GuardianPayload { name: "Alan".to_string(), ..Default::default() }), Err(Error::ValidationFailed { .. })));
+
+        link_guardian(student.id, guardian.id).ok().unwrap();
+        assert!(matches!(link_guardian(student.id, guardian.id), Err(Error::Conflict { .. })));
+        let names = |guardians: Vec<Guardian>| guardians.into_iter().map(|guardian| guardian.name).collect::<Vec<_>>();
+        assert_eq!(names(get_guardians_for_student(student.id).ok().unwrap()), vec!["Grace"]);
+
+        let moved = GuardianPatch {
+            principal: Some(Some(user(2))),
+            ..Default::default()
+        };
+        update_guardian(guardian.id, moved).ok().unwrap();
+        assert!(roles_of(user(1)).is_empty());
+        assert_eq!(roles_of(user(2)), vec![Role::Guardian { guardian_id: guardian.id }]);
+
+        delete_guardian(guardian.id, "Moved away".to_string()).ok().unwrap();
+        assert!(get_guardians_for_student(student.id).ok().unwrap().is_empty());
+        purge_guardian(guardian.id).ok().unwrap();
+        assert!(roles_of(user(2)).is_empty());
+    }
+
+    /// A role set stored before guardian roles named their guardian, holding a role for
+    /// teacher 2 and a guardian role.
+    const LEGACY_TEACHER_AND_GUARDIAN_ROLES: &str = concat!(
+        "4449444c056c01bdb8fcea0e016d026b0582a0d09502038dbebae7057f9ba98ee90704f1cb89e8087fefb8",
+        "e0fb0a7f6c019896ffa002786c019fd8f1f2057801000200020000000000000003",
+    );
+
+    /// A role set stored before guardian roles named their guardian, holding only a guardian role.
+    const LEGACY_GUARDIAN_ROLE: &str = concat!(
+        "4449444c056c01bdb8fcea0e016d026b0582a0d09502038dbebae7057f9ba98ee90704f1cb89e8087fefb8",
+        "e0fb0a7f6c019896ffa002786c019fd8f1f2057801000103",
+    );
+
+    fn hex(text: &str) -> Vec<u8> {
+        (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn legacy_role_sets_decode_without_their_guardian_roles() {
+        let roles = RoleSet::from_bytes(Cow::Owned(hex(LEGACY_TEACHER_AND_GUARDIAN_ROLES))).roles;
+        assert_eq!(roles, vec![Role::Teacher { teacher_id: TeacherId::from(2) }]);
+        assert!(RoleSet::from_bytes(Cow::Owned(hex(LEGACY_GUARDIAN_ROLE))).roles.is_empty());
+
+        let current = RoleSet {
+            roles: vec![Role::Admin, Role::Guardian { guardian_id: GuardianId::from(4) }],
+        };
+        assert_eq!(RoleSet::from_bytes(current.to_bytes()).roles, current.roles);
+        assert!(!migration::RoleSetV1::from_bytes(current.to_bytes()).has_guardian_role());
+    }
+
+    #[test]
+    fn migration_drops_legacy_guardian_roles() {
+        let store = |principal: Principal, bytes: Vec<u8>| {
+            let mut raw_roles: StableBTreeMap<PrincipalKey, Blob<512>, Memory> =
+                StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(23))));
+            raw_roles.insert(PrincipalKey(principal), Blob::try_from(bytes.as_slice()).unwrap());
+        };
+        store(user(1), hex(LEGACY_TEACHER_AND_GUARDIAN_ROLES));
+        store(user(2), hex(LEGACY_GUARDIAN_ROLE));
+        grant_role(user(3), Role::Registrar).ok().unwrap();
+
+        migrate_guardian_roles();
+        let principals: Vec<Principal> =
+            ROLE_STORAGE.with(|storage| storage.borrow().iter().map(|(key, _)| key.0).collect());
+        assert_eq!(principals, vec![user(1), user(3)]);
+        assert_eq!(roles_of(user(1)), vec![Role::Teacher { teacher_id: TeacherId::from(2) }]);
+        let dropped: Vec<String> = audit_entries(AuditFilter { endpoint: Some("post_upgrade".to_string()), ..Default::default() })
+            .into_iter()
+            .filter_map(|entry| entry.before)
+            .collect();
+        assert_eq!(dropped.len(), 2);
+        assert!(dropped[1].contains(r#""roles":["Guardian"]"#));
+    }
+
+    #[test]
+    fn scopes_the_guardian_portal_to_linked_children() {
+        let child = new_student("Ada");
+        let other = new_student("Alan");
+        let room = new_classroom("Room 1");
+        let course = new_course("Algebra");
+        enroll_student(child.id, course.id).ok().unwrap();
+        add_schedule_slot(course.id, slot(&room, DayOfWeek::Monday, at(9, 0), at(10, 0))).ok().unwrap();
+        mark_attendance(attendance(&child, &course, date(2025, 9, 1))).ok().unwrap();
+        let guardian = new_guardian("Grace", Some(user(1)));
+        link_guardian(child.id, guardian.id).ok().unwrap();
+        grant_role(user(2), Role::Registrar).ok().unwrap();
+
+        act_as(user(1));
+        let children: Vec<StudentId> = my_children().ok().unwrap().iter().map(|student| student.id).collect();
+        assert_eq!(children, vec![child.id]);
+        assert_eq!(child_attendance(child.id, None, None).ok().unwrap().len(), 1);
+        assert!(child_grades(child.id, None).ok().unwrap().is_empty());
+        assert_eq!(child_timetable(child.id, date(2025, 9, 3)).ok().unwrap().len(), 1);
+        assert!(matches!(child_attendance(other.id, None, None), Err(Error::Unauthorized { .. })));
+        assert!(matches!(child_grades(other.id, None), Err(Error::Unauthorized { .. })));
+        assert!(matches!(get_student(child.id), Err(Error::Unauthorized { .. })));
+
+        act_as(user(2));
+        assert!(matches!(my_children(), Err(Error::Unauthorized { .. })));
+        act_as(Principal::anonymous());
+        assert!(matches!(child_timetable(child.id, date(2025, 9, 3)), Err(Error::Unauthorized { .. })));
+    }
+
+
+    fn audit_entries(filter: AuditFilter) -> Vec<AuditEntry> {
+        get_audit_log(filter, PageRequest::default()).ok().unwrap().items
+    }
+
+    fn audited(endpoint: &str) -> Vec<(String, Option<u64>)> {
+        let filter = AuditFilter { endpoint: Some(endpoint.to_string()), ..Default::default() };
+        audit_entries(filter).into_iter().map(|entry| (entry.entity, entry.entity_id)).collect()
+    }
+
+    #[test]
+    fn audits_one_entry_per_changed_record() {
+        let student = new_student("Ada");
+        let course = new_course("Algebra");
+        let first = new_teacher("Alan");
+        let second = new_teacher("Grace");
+        enroll_student(student.id, course.id).ok().unwrap();
+        assign_teacher_to_course(first.id, course.id).ok().unwrap();
+        assign_teacher_to_course(second.id, course.id).ok().unwrap();
+        drop_student(student.id, course.id).ok().unwrap();
+
+        let both = |endpoint: &str| {
+            assert_eq!(
+                audited(endpoint),
+                vec![("Student".to_string(), Some(student.id.into())), ("Course".to_string(), Some(course.id.into()))]
+            );
+        };
+        both("enroll_student");
+        both("drop_student");
+        assert_eq!(
+            audited("assign_teacher_to_course"),
+            vec![
+                ("Teacher".to_string(), Some(first.id.into())),
+                ("Course".to_string(), Some(course.id.into())),
+                ("Teacher".to_string(), Some(first.id.into())),
+                ("Teacher".to_string(), Some(second.id.into())),
+                ("Course".to_string(), Some(course.id.into())),
+            ]
+        );
+
+        let entry = audit_entries(AuditFilter { endpoint: Some("enroll_student".to_string()), ..Default::default() })
+            .remove(0);
+        assert_eq!(entry.caller, controller());
+        assert!(entry.before.unwrap().contains("\"enrolled_courses\":[]"));
+        assert!(entry.after.unwrap().contains(&format!("\"enrolled_courses\":[{}]", course.id)));
+    }
+
+    #[test]
+    fn audits_role_changes_only_when_the_roles_change() {
+        grant_role(user(1), Role::Registrar).ok().unwrap();
+        grant_role(user(1), Role::Registrar).ok().unwrap();
+        assert!(revoke_role(user(1), Role::Admin).is_err());
+        revoke_role(user(1), Role::Registrar).ok().unwrap();
+
+        assert_eq!(audited("grant_role").len(), 1);
+        let revoked = audit_entries(AuditFilter { entity: Some("Role".to_string()), ..Default::default() });
+        assert_eq!(revoked.len(), 2);
+        assert_eq!(revoked[1].after.as_deref(), Some(&*format!(r#"{{"principal":"{}","roles":[]}}"#, user(1))));
+
+        act_as(user(2));
+        assert!(matches!(get_audit_log(AuditFilter::default(), PageRequest::default()), Err(Error::Unauthorized { .. })));
+    }
+
+
+    #[test]
+    fn records_who_created_and_last_modified_each_record() {
+        let created_at = time();
+        let ada = new_student("Ada");
+        let alan = new_student("Alan");
+        let course = new_course("Algebra");
+        assert_eq!(ada.metadata.created_at, created_at);
+        assert_eq!(ada.metadata.updated_at, created_at);
+        assert_eq!(ada.metadata.updated_by, controller());
+
+        grant_role(user(1), Role::Registrar).ok().unwrap();
+        advance_time(1_000_000_000);
+        act_as(user(1));
+        let patch = StudentPatch { name: Some("Ada Lovelace".to_string()), ..Default::default() };
+        let ada = update_student(ada.id, patch).ok().unwrap();
+        assert_eq!(ada.metadata.created_at, created_at);
+        assert_eq!(ada.metadata.updated_at, time());
+        assert_eq!(ada.metadata.updated_by, user(1));
+
+        enroll_student(alan.id, course.id).ok().unwrap();
+        assert_eq!(get_course(course.id).ok().unwrap().metadata.updated_by, user(1));
+
+        let modified: Vec<StudentId> =
+            list_students(PageRequest::default(), Some(time())).ok().unwrap().items.iter().map(|student| student.id).collect();
+        assert_eq!(modified, vec![ada.id, alan.id]);
+        assert!(list_students(PageRequest::default(), Some(time() + 1)).ok().unwrap().items.is_empty());
+        assert_eq!(list_students(PageRequest::default(), Some(created_at)).ok().unwrap().items.len(), 2);
+        assert_eq!(list_courses(PageRequest::default(), Some(time())).ok().unwrap().items.len(), 1);
+    }
+
+
+    #[test]
+    fn archives_restores_and_purges_deleted_records() {
+        let student = new_student("Ada");
+        assert_eq!(invalid_fields(delete_student(student.id, " ".to_string(), None)), vec!["reason"]);
+        delete_student(student.id, "Left the school".to_string(), None).ok().unwrap();
+        assert_eq!(get_student(student.id).err(), Some(Error::not_found("Student", student.id)));
+        assert!(find_student_by_email("ada@example.com".to_string()).ok().flatten().is_none());
+
+        let archived = list_archived_students(PageRequest::default()).ok().unwrap().items;
+        assert_eq!(archived.len(), 1);
+        assert_eq!(archived[0].record.id, student.id);
+        assert_eq!(archived[0].archive.reason, "Left the school");
+        assert_eq!(archived[0].archive.archived_by, controller());
+
+        let restored = restore_student(student.id).ok().unwrap();
+        assert_eq!(restored.name, "Ada");
+        assert_eq!(find_student_by_email("ada@example.com".to_string()).ok().flatten().map(|found| found.id), Some(student.id));
+        assert!(list_archived_students(PageRequest::default()).ok().unwrap().items.is_empty());
+        assert_eq!(restore_student(student.id).err(), Some(Error::not_found("Student", student.id)));
+
+        delete_student(student.id, "Duplicate".to_string(), None).ok().unwrap();
+        grant_role(user(1), Role::Registrar).ok().unwrap();
+        act_as(user(1));
+        assert!(matches!(purge_student(student.id), Err(Error::Unauthorized { .. })));
+        act_as(controller());
+        purge_student(student.id).ok().unwrap();
+        assert!(list_archived_students(PageRequest::default()).ok().unwrap().items.is_empty());
+        assert_eq!(restore_student(student.id).err(), Some(Error::not_found("Student", student.id)));
+    }
+
+    #[test]
+    fn restores_a_student_only_while_its_email_is_free() {
+        let student = new_student("Ada");
+        delete_student(student.id, "Left the school".to_string(), None).ok().unwrap();
+        let newcomer = new_student("Ada");
+
+        assert!(matches!(restore_student(student.id), Err(Error::EmailInUse { .. })));
+        assert_eq!(list_archived_students(PageRequest::default()).ok().unwrap().items.len(), 1);
+        delete_student(newcomer.id, "Duplicate".to_string(), None).ok().unwrap();
+        assert!(restore_student(student.id).is_ok());
+    }
+
+    #[test]
+    fn archived_guardians_keep_their_links_but_lose_the_portal() {
+        let child = new_student("Ada");
+        let guardian = new_guardian("Grace", Some(user(1)));
+        link_guardian(child.id, guardian.id).ok().unwrap();
+        delete_guardian(guardian.id, "Moved away".to_string()).ok().unwrap();
+
+        assert!(get_guardians_for_student(child.id).ok().unwrap().is_empty());
+        act_as(user(1));
+        assert!(matches!(my_children(), Err(Error::Unauthorized { .. })));
+
+        act_as(controller());
+        restore_guardian(guardian.id).ok().unwrap();
+        assert_eq!(get_guardians_for_student(child.id).ok().unwrap().len(), 1);
+        act_as(user(1));
+        assert_eq!(my_children().ok().unwrap().len(), 1);
+    }
+
+
+    fn refs(references: &[RecordRef]) -> Vec<(&str, u64)> {
+        references.iter().map(|reference| (reference.entity.as_str(), reference.id)).collect()
+    }
+
+    #[test]
+    fn restricts_deletes_of_referenced_records_by_default() {
+        let student = new_student("Ada");
+        let course = new_course("Algebra");
+        enroll_student(student.id, course.id).ok().unwrap();
+
+        assert!(matches!(delete_course(course.id, "Cancelled".to_string(), None), Err(Error::Conflict { .. })));
+        assert!(matches!(
+            delete_student(student.id, "Left the school".to_string(), Some(DeletePolicy::Restrict)),
+            Err(Error::Conflict { .. })
+        ));
+        assert_eq!(get_course(course.id).ok().unwrap().student_ids, vec![student.id]);
+
+        let summary = delete_student(student.id, "Left the school".to_string(), Some(DeletePolicy::Nullify)).ok().unwrap();
+        assert_eq!(refs(&summary.archived), vec![("Student", student.id.into())]);
+        assert_eq!(refs(&summary.updated), vec![("Course", course.id.into())]);
+        assert!(get_course(course.id).ok().unwrap().student_ids.is_empty());
+    }
+
+    #[test]
+    fn nullifies_every_reference_to_a_deleted_course() {
+        let student = new_student("Ada");
+        let teacher = new_teacher("Grace");
+        let course = new_course("Algebra");
+        let room = add_classroom(ClassroomPayload {
+            current_course_id: Some(course.id),
+            ..ClassroomPayload::from(&new_classroom("Room 1"))
+        })
+        .ok()
+        .unwrap();
+        enroll_student(student.id, course.id).ok().unwrap();
+        assign_teacher_to_course(teacher.id, course.id).ok().unwrap();
+        let slot = add_schedule_slot(course.id, slot(&room, DayOfWeek::Monday, at(9, 0), at(10, 0))).ok().unwrap();
+
+        let summary = delete_course(course.id, "Cancelled".to_string(), Some(DeletePolicy::Nullify)).ok().unwrap();
+        assert_eq!(refs(&summary.archived), vec![("Course", course.id.into())]);
+        assert_eq!(
+            refs(&summary.updated),
+            vec![("Student", student.id.into()), ("Teacher", teacher.id.into()), ("Classroom", room.id.into())]
+        );
+        assert_eq!(summary.removed_slots, vec![slot.id]);
+
+        assert!(get_student(student.id).ok().unwrap().enrolled_courses.is_empty());
+        let teacher = get_teacher(teacher.id).ok().unwrap();
+        assert!(teacher.assigned_courses.is_empty() && teacher.schedule.is_empty());
+        assert_eq!(get_classroom(room.id).ok().unwrap().current_course_id, None);
+        assert!(get_course_timetable(course.id).is_err());
+    }
+
+    #[test]
+    fn cascades_a_teacher_delete_to_its_courses() {
+        let teacher = new_teacher("Grace");
+        let algebra = new_course("Algebra");
+        let physics = new_course("Physics");
+        assign_teacher_to_course(teacher.id, algebra.id).ok().unwrap();
+        assign_teacher_to_course(teacher.id, physics.id).ok().unwrap();
+
+        let summary = delete_teacher(teacher.id, "Retired".to_string(), Some(DeletePolicy::Cascade)).ok().unwrap();
+        assert_eq!(
+            refs(&summary.archived),
+            vec![("Teacher", teacher.id.into()), ("Course", algebra.id.into()), ("Course", physics.id.into())]
+        );
+        assert!(get_course(algebra.id).is_err() && get_course(physics.id).is_err());
+        let archived = list_archived_courses(PageRequest::default()).ok().unwrap().items;
+        assert!(archived.iter().all(|course| course.record.teacher_id.is_none() && course.archive.reason == "Retired"));
+
+        let other = new_teacher("Alan");
+        let course = new_course("Chemistry");
+        assign_teacher_to_course(other.id, course.id).ok().unwrap();
+        let summary = delete_teacher(other.id, "Retired".to_string(), Some(DeletePolicy::Nullify)).ok().unwrap();
+        assert_eq!(refs(&summary.updated), vec![("Course", course.id.into())]);
+        assert_eq!(get_course(course.id).ok().unwrap().teacher_id, None);
+    }
+
+    #[test]
+    fn course_patches_can_remove_the_teacher() {
+        let mut payload = CoursePayload {
+            teacher_id: Some(TeacherId::from(2)),
+            ..Default::default()
+        };
+        CoursePatch::default().apply_to(&mut payload);
+        assert_eq!(payload.teacher_id, Some(TeacherId::from(2)));
+
+        let patch = CoursePatch {
+            teacher_id: Some(None),
+            ..Default::default()
+        };
+        patch.apply_to(&mut payload);
+        assert_eq!(payload.teacher_id, None);
+    }
+
+    #[test]
+    fn classroom_patches_can_remove_the_current_course() {
+        let mut payload = ClassroomPayload {
+            current_course_id: Some(CourseId::from(3)),
+            ..Default::default()
+        };
+        ClassroomPatch::default().apply_to(&mut payload);
+        assert_eq!(payload.current_course_id, Some(CourseId::from(3)));
+
+        let patch = ClassroomPatch {
+            current_course_id: Some(None),
+            ..Default::default()
+        };
+        patch.apply_to(&mut payload);
+        assert_eq!(payload.current_course_id, None);
+    }
+
+
+    #[test]
+    fn rejects_references_to_missing_records_without_using_an_id() {
+        let missing = CoursePayload {
+            name: "Algebra".to_string(),
+            description: "Algebra course".to_string(),
+            teacher_id: Some(TeacherId::from(9)),
+        };
+        assert_eq!(add_course(missing).err(), Some(Error::not_found("Teacher", 9u64)));
+        let room = ClassroomPayload {
+            name: "Room 1".to_string(),
+            location: "Main building".to_string(),
+            capacity: 30,
+            current_course_id: Some(CourseId::from(9)),
+        };
+        assert_eq!(add_classroom(room).err(), Some(Error::not_found("Course", 9u64)));
+
+        assert_eq!(new_course("Algebra").id, CourseId::from(1));
+        let classroom = new_classroom("Room 1");
+        assert_eq!(classroom.id, ClassroomId::from(1));
+        let patch = ClassroomPatch { current_course_id: Some(Some(CourseId::from(9))), ..Default::default() };
+        assert_eq!(update_classroom(classroom.id, patch).err(), Some(Error::not_found("Course", 9u64)));
+    }
+
+    #[test]
+    fn keeps_the_assigned_courses_in_step_with_the_course_teacher() {
+        let grace = new_teacher("Grace");
+        let alan = new_teacher("Alan");
+        let course = add_course(CoursePayload {
+            name: "Algebra".to_string(),
+            description: "Algebra course".to_string(),
+            teacher_id: Some(grace.id),
+        })
+        .ok()
+        .unwrap();
+        assert_eq!(get_teacher(grace.id).ok().unwrap().assigned_courses, vec![course.id]);
+
+        let moved = CoursePatch { teacher_id: Some(Some(alan.id)), ..Default::default() };
+        assert_eq!(update_course(course.id, moved).ok().unwrap().teacher_id, Some(alan.id));
+        assert!(get_teacher(grace.id).ok().unwrap().assigned_courses.is_empty());
+        assert_eq!(get_teacher(alan.id).ok().unwrap().assigned_courses, vec![course.id]);
+
+        let cleared = CoursePatch { teacher_id: Some(None), ..Default::default() };
+        assert_eq!(update_course(course.id, cleared).ok().unwrap().teacher_id, None);
+        assert!(get_teacher(alan.id).ok().unwrap().assigned_courses.is_empty());
+    }
+}
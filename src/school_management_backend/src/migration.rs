@@ -0,0 +1,1691 @@
+//! Versioned encoding of the stored records, and the migrations from their older layouts.
+//!
+//! A record is stored as an envelope: `ENVELOPE_MAGIC`, a version byte, then the Candid
+//! encoding of the record in that version. Records written before envelopes existed are plain
+//! Candid, which always starts with `DIDL`. Their layout is recognized by decoding them, since
+//! every older layout lacks a field of the newer ones or holds one of another type.
+//!
+//! To change a stored record, keep its current layout here as the next `...V<n>` struct, add a
+//! `From` migration from it to the new layout, bump `VERSION` and `STORAGE_LAYOUT_VERSION`.
+
+use crate::{
+    ArchiveInfo, Archived, AttendanceRecord, AuditEntry, Classroom, ClassroomId, Course, CourseId, Date,
+    GradingScale, Guardian, GuardianId, Metadata, PerformanceRecord, Relationship, Role, RoleSet,
+    ScheduleSlot, SchoolSettings, Student, StudentId, Teacher, TeacherId,
+};
+use candid::{CandidType, Decode, Encode, Principal};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Marks an enveloped record.
+const ENVELOPE_MAGIC: &[u8] = b"SMVR";
+
+/// Version of the layout of the stored maps as a whole. Bumping it makes `post_upgrade` rewrite
+/// every record in its current version.
+pub const STORAGE_LAYOUT_VERSION: u64 = 2;
+
+/// Implemented by the records stored in versioned envelopes.
+pub trait Versioned: CandidType + DeserializeOwned {
+    /// Version the record is written in.
+    const VERSION: u8;
+
+    /// Name of the record in decoding errors.
+    const NAME: &'static str;
+
+    /// Decodes and migrates a record written in an older version, or before envelopes existed
+    /// when `version` is None.
+    fn decode_older(version: Option<u8>, bytes: &[u8]) -> Result<Self, candid::Error>;
+
+    /// Decodes and migrates an archived record written before envelopes existed, for records
+    /// whose layout changed while they could be archived.
+    fn decode_unversioned_archive(bytes: &[u8]) -> Result<Archived<Self>, candid::Error> {
+        Decode!(bytes, Archived<Self>)
+    }
+}
+
+/// Returns whether the bytes hold an enveloped record.
+pub fn is_enveloped(bytes: &[u8]) -> bool {
+    bytes.starts_with(ENVELOPE_MAGIC)
+}
+
+fn envelope(version: u8, payload: Vec<u8>) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(ENVELOPE_MAGIC.len() + 1 + payload.len());
+    bytes.extend_from_slice(ENVELOPE_MAGIC);
+    bytes.push(version);
+    bytes.extend(payload);
+    bytes
+}
+
+/// Encodes a record in its current version.
+pub fn encode<T: Versioned>(record: &T) -> Vec<u8> {
+    envelope(T::VERSION, Encode!(record).unwrap())
+}
+
+/// Decodes a record of any version, migrating it to the current one.
+///
+/// Panics, trapping the call, if the bytes hold no known version of the record.
+pub fn decode<T: Versioned>(bytes: &[u8]) -> T {
+    let result = match bytes.strip_prefix(ENVELOPE_MAGIC) {
+        Some([version, payload @ ..]) if *version == T::VERSION => Decode!(payload, T),
+        Some([version, payload @ ..]) => T::decode_older(Some(*version), payload),
+        Some([]) => Err(candid::Error::msg("The envelope has no version")),
+        None => T::decode_older(None, bytes),
+    };
+    result.unwrap_or_else(|error| panic!("Cannot decode a stored {}: {}", T::NAME, error))
+}
+
+fn unknown_version(name: &str, version: Option<u8>) -> candid::Error {
+    match version {
+        Some(version) => candid::Error::msg(format!("Unknown {} version {}", name, version)),
+        None => candid::Error::msg(format!("Unknown unversioned {} layout", name)),
+    }
+}
+
+/// Represents an archived record as stored, with the record in its own envelope so that it
+/// migrates like the live records of its kind.
+#[derive(CandidType, Deserialize)]
+struct StoredArchive {
+    record: Vec<u8>,
+    archive: ArchiveInfo,
+}
+
+/// Version of the `StoredArchive` layout.
+const ARCHIVE_VERSION: u8 = 1;
+
+/// Encodes an archived record.
+pub fn encode_archived<T: Versioned>(archived: &Archived<T>) -> Vec<u8> {
+    let stored = StoredArchive {
+        record: encode(&archived.record),
+        archive: archived.archive.clone(),
+    };
+    envelope(ARCHIVE_VERSION, Encode!(&stored).unwrap())
+}
+
+/// Decodes an archived record, migrating the record to its current version.
+pub fn decode_archived<T: Versioned>(bytes: &[u8]) -> Archived<T> {
+    let result = match bytes.strip_prefix(ENVELOPE_MAGIC) {
+        Some([ARCHIVE_VERSION, payload @ ..]) => Decode!(payload, StoredArchive).map(|stored| Archived {
+            record: decode(&stored.record),
+            archive: stored.archive,
+        }),
+        Some([version, ..]) => Err(unknown_version("archive", Some(*version))),
+        Some([]) => Err(candid::Error::msg("The envelope has no version")),
+        None => T::decode_unversioned_archive(bytes),
+    };
+    result.unwrap_or_else(|error| panic!("Cannot decode a stored archived {}: {}", T::NAME, error))
+}
+
+/// Migrates the record of an archive written before envelopes existed.
+fn migrate_archived<R, T: From<R>>(archived: Archived<R>) -> Archived<T> {
+    Archived {
+        record: T::from(archived.record),
+        archive: archived.archive,
+    }
+}
+
+/// Represents a student as stored while dates of birth were free text. The free-text guardian
+/// details and the ID lists of attendance and performance records of even older layouts are
+/// skipped by Candid; the details are moved into guardian records by `migrate_guardian_details`.
+#[derive(CandidType, Deserialize)]
+struct StudentV1 {
+    id: StudentId,
+    name: String,
+    grade_level: u8,
+    enrolled_courses: Vec<CourseId>,
+    email: String,
+    date_of_birth: String, // Format: YYYY-MM-DD
+    address: String,
+}
+
+/// Represents a student as stored before it carried metadata.
+#[derive(CandidType, Deserialize)]
+struct StudentV2 {
+    id: StudentId,
+    name: String,
+    grade_level: u8,
+    enrolled_courses: Vec<CourseId>,
+    email: String,
+    date_of_birth: Date,
+    address: String,
+}
+
+impl From<StudentV1> for StudentV2 {
+    fn from(student: StudentV1) -> Self {
+        StudentV2 {
+            id: student.id,
+            name: student.name,
+            grade_level: student.grade_level,
+            enrolled_courses: student.enrolled_courses,
+            email: student.email,
+            // Invalid dates are recorded in the audit log by `audit_invalid_legacy_dates`.
+            date_of_birth: Date::parse(&student.date_of_birth).unwrap_or_default(),
+            address: student.address,
+        }
+    }
+}
+
+impl From<StudentV2> for Student {
+    fn from(student: StudentV2) -> Self {
+        Student {
+            id: student.id,
+            name: student.name,
+            grade_level: student.grade_level,
+            enrolled_courses: student.enrolled_courses,
+            email: student.email,
+            date_of_birth: student.date_of_birth,
+            address: student.address,
+            metadata: Metadata::default(),
+        }
+    }
+}
+
+impl Versioned for Student {
+    const VERSION: u8 = 3;
+    const NAME: &'static str = "student";
+
+    fn decode_older(version: Option<u8>, bytes: &[u8]) -> Result<Self, candid::Error> {
+        match version {
+            Some(1) => Decode!(bytes, StudentV1).map(|student| Student::from(StudentV2::from(student))),
+            Some(2) => Decode!(bytes, StudentV2).map(Student::from),
+            None => Decode!(bytes, Student)
+                .or_else(|_| Self::decode_older(Some(2), bytes))
+                .or_else(|_| Self::decode_older(Some(1), bytes))
+                .map_err(|_| unknown_version(Self::NAME, None)),
+            _ => Err(unknown_version(Self::NAME, version)),
+        }
+    }
+}
+
+/// Represents a teacher as stored while employment dates were free text.
+#[derive(CandidType, Deserialize)]
+struct TeacherV1 {
+    id: TeacherId,
+    name: String,
+    subject_area: String,
+    assigned_courses: Vec<CourseId>,
+    email: String,
+    qualifications: String,
+    employment_date: String, // Format: YYYY-MM-DD
+    address: String,
+    schedule: Vec<u64>,
+}
+
+/// Represents a teacher as stored before it carried metadata.
+#[derive(CandidType, Deserialize)]
+struct TeacherV2 {
+    id: TeacherId,
+    name: String,
+    subject_area: String,
+    assigned_courses: Vec<CourseId>,
+    email: String,
+    qualifications: String,
+    employment_date: Date,
+    address: String,
+    schedule: Vec<u64>,
+}
+
+impl From<TeacherV1> for TeacherV2 {
+    fn from(teacher: TeacherV1) -> Self {
+        TeacherV2 {
+            id: teacher.id,
+            name: teacher.name,
+            subject_area: teacher.subject_area,
+            assigned_courses: teacher.assigned_courses,
+            email: teacher.email,
+            qualifications: teacher.qualifications,
+            // Invalid dates are recorded in the audit log by `audit_invalid_legacy_dates`.
+            employment_date: Date::parse(&teacher.employment_date).unwrap_or_default(),
+            address: teacher.address,
+            schedule: teacher.schedule,
+        }
+    }
+}
+
+impl From<TeacherV2> for Teacher {
+    fn from(teacher: TeacherV2) -> Self {
+        Teacher {
+            id: teacher.id,
+            name: teacher.name,
+            subject_area: teacher.subject_area,
+            assigned_courses: teacher.assigned_courses,
+            email: teacher.email,
+            qualifications: teacher.qualifications,
+            employment_date: teacher.employment_date,
+            address: teacher.address,
+            schedule: teacher.schedule,
+            metadata: Metadata::default(),
+        }
+    }
+}
+
+impl Versioned for Teacher {
+    const VERSION: u8 = 3;
+    const NAME: &'static str = "teacher";
+
+    fn decode_older(version: Option<u8>, bytes: &[u8]) -> Result<Self, candid::Error> {
+        match version {
+            Some(1) => Decode!(bytes, TeacherV1).map(|teacher| Teacher::from(TeacherV2::from(teacher))),
+            Some(2) => Decode!(bytes, TeacherV2).map(Teacher::from),
+            None => Decode!(bytes, Teacher)
+                .or_else(|_| Self::decode_older(Some(2), bytes))
+                .or_else(|_| Self::decode_older(Some(1), bytes))
+                .map_err(|_| unknown_version(Self::NAME, None)),
+            _ => Err(unknown_version(Self::NAME, version)),
+        }
+    }
+}
+
+/// Represents a course as stored before it carried metadata. The free-text schedule of the
+/// oldest layout is skipped by Candid; it was replaced by schedule slots.
+#[derive(CandidType, Deserialize)]
+struct CourseV1 {
+    id: CourseId,
+    name: String,
+    description: String,
+    teacher_id: TeacherId,
+    student_ids: Vec<StudentId>,
+    syllabus: String,
+    course_materials: Vec<String>,
+}
+
+/// Represents a course as stored while every course named a teacher, 0 standing for none.
+#[derive(CandidType, Deserialize)]
+struct CourseV2 {
+    id: CourseId,
+    name: String,
+    description: String,
+    teacher_id: TeacherId,
+    student_ids: Vec<StudentId>,
+    syllabus: String,
+    course_materials: Vec<String>,
+    metadata: Metadata,
+}
+
+impl From<CourseV1> for CourseV2 {
+    fn from(course: CourseV1) -> Self {
+        CourseV2 {
+            id: course.id,
+            name: course.name,
+            description: course.description,
+            teacher_id: course.teacher_id,
+            student_ids: course.student_ids,
+            syllabus: course.syllabus,
+            course_materials: course.course_materials,
+            metadata: Metadata::default(),
+        }
+    }
+}
+
+impl From<CourseV2> for Course {
+    fn from(course: CourseV2) -> Self {
+        Course {
+            id: course.id,
+            name: course.name,
+            description: course.description,
+            teacher_id: Some(course.teacher_id).filter(|teacher_id| u64::from(*teacher_id) != 0),
+            student_ids: course.student_ids,
+            syllabus: course.syllabus,
+            course_materials: course.course_materials,
+            metadata: course.metadata,
+        }
+    }
+}
+
+impl Versioned for Course {
+    const VERSION: u8 = 3;
+    const NAME: &'static str = "course";
+
+    fn decode_older(version: Option<u8>, bytes: &[u8]) -> Result<Self, candid::Error> {
+        match version {
+            Some(1) => Decode!(bytes, CourseV1).map(|course| Course::from(CourseV2::from(course))),
+            Some(2) => Decode!(bytes, CourseV2).map(Course::from),
+            // Version 2 goes first: the current layout also accepts its teacher ID, but as
+            // Some(0) for a course without a teacher.
+            None => Self::decode_older(Some(2), bytes)
+                .or_else(|_| Decode!(bytes, Course))
+                .or_else(|_| Self::decode_older(Some(1), bytes))
+                .map_err(|_| unknown_version(Self::NAME, None)),
+            _ => Err(unknown_version(Self::NAME, version)),
+        }
+    }
+
+    fn decode_unversioned_archive(bytes: &[u8]) -> Result<Archived<Self>, candid::Error> {
+        // Courses were archived from version 2 on, which goes first as for the records.
+        Decode!(bytes, Archived<CourseV2>)
+            .map(migrate_archived)
+            .or_else(|_| Decode!(bytes, Archived<Course>))
+    }
+}
+
+/// Represents a classroom as stored before it carried metadata.
+#[derive(CandidType, Deserialize)]
+struct ClassroomV1 {
+    id: ClassroomId,
+    name: String,
+    location: String,
+    capacity: u32,
+    current_course_id: CourseId,
+    equipment: Vec<String>,
+}
+
+/// Represents a classroom as stored while every classroom named a current course, 0 standing
+/// for none.
+#[derive(CandidType, Deserialize)]
+struct ClassroomV2 {
+    id: ClassroomId,
+    name: String,
+    location: String,
+    capacity: u32,
+    current_course_id: CourseId,
+    equipment: Vec<String>,
+    metadata: Metadata,
+}
+
+impl From<ClassroomV1> for ClassroomV2 {
+    fn from(classroom: ClassroomV1) -> Self {
+        ClassroomV2 {
+            id: classroom.id,
+            name: classroom.name,
+            location: classroom.location,
+            capacity: classroom.capacity,
+            current_course_id: classroom.current_course_id,
+            equipment: classroom.equipment,
+            metadata: Metadata::default(),
+        }
+    }
+}
+
+impl From<ClassroomV2> for Classroom {
+    fn from(classroom: ClassroomV2) -> Self {
+        Classroom {
+            id: classroom.id,
+            name: classroom.name,
+            location: classroom.location,
+            capacity: classroom.capacity,
+            current_course_id: Some(classroom.current_course_id)
+                .filter(|course_id| u64::from(*course_id) != 0),
+            equipment: classroom.equipment,
+            metadata: classroom.metadata,
+        }
+    }
+}
+
+impl Versioned for Classroom {
+    const VERSION: u8 = 3;
+    const NAME: &'static str = "classroom";
+
+    fn decode_older(version: Option<u8>, bytes: &[u8]) -> Result<Self, candid::Error> {
+        match version {
+            Some(1) => {
+                Decode!(bytes, ClassroomV1).map(|classroom| Classroom::from(ClassroomV2::from(classroom)))
+            }
+            Some(2) => Decode!(bytes, ClassroomV2).map(Classroom::from),
+            // Version 2 goes first, as for courses.
+            None => Self::decode_older(Some(2), bytes)
+                .or_else(|_| Decode!(bytes, Classroom))
+                .or_else(|_| Self::decode_older(Some(1), bytes))
+                .map_err(|_| unknown_version(Self::NAME, None)),
+            _ => Err(unknown_version(Self::NAME, version)),
+        }
+    }
+
+    fn decode_unversioned_archive(bytes: &[u8]) -> Result<Archived<Self>, candid::Error> {
+        // Classrooms were archived from version 2 on, as courses were.
+        Decode!(bytes, Archived<ClassroomV2>)
+            .map(migrate_archived)
+            .or_else(|_| Decode!(bytes, Archived<Classroom>))
+    }
+}
+
+/// Represents a guardian as stored before it carried metadata.
+#[derive(CandidType, Deserialize)]
+struct GuardianV1 {
+    id: GuardianId,
+    name: String,
+    relationship: Relationship,
+    phone: String,
+    email: String,
+    principal: Option<Principal>,
+    has_custody: bool,
+    can_pick_up: bool,
+    notes: String,
+}
+
+impl From<GuardianV1> for Guardian {
+    fn from(guardian: GuardianV1) -> Self {
+        Guardian {
+            id: guardian.id,
+            name: guardian.name,
+            relationship: guardian.relationship,
+            phone: guardian.phone,
+            email: guardian.email,
+            principal: guardian.principal,
+            has_custody: guardian.has_custody,
+            can_pick_up: guardian.can_pick_up,
+            notes: guardian.notes,
+            metadata: Metadata::default(),
+        }
+    }
+}
+
+impl Versioned for Guardian {
+    const VERSION: u8 = 2;
+    const NAME: &'static str = "guardian";
+
+    fn decode_older(version: Option<u8>, bytes: &[u8]) -> Result<Self, candid::Error> {
+        match version {
+            Some(1) => Decode!(bytes, GuardianV1).map(Guardian::from),
+            None => Decode!(bytes, Guardian)
+                .or_else(|_| Self::decode_older(Some(1), bytes))
+                .map_err(|_| unknown_version(Self::NAME, None)),
+            _ => Err(unknown_version(Self::NAME, version)),
+        }
+    }
+}
+
+/// Represents a role as stored before guardian roles named their guardian.
+#[derive(CandidType, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum RoleV1 {
+    Admin,
+    Registrar,
+    Teacher { teacher_id: TeacherId },
+    Student { student_id: StudentId },
+    Guardian,
+}
+
+/// Represents the roles of a principal as stored before guardian roles named their guardian.
+/// Its guardian roles are dropped by `migrate_guardian_roles`, which records them first.
+#[derive(CandidType, Deserialize, Default)]
+pub(crate) struct RoleSetV1 {
+    pub(crate) roles: Vec<RoleV1>,
+}
+
+impl RoleSetV1 {
+    pub(crate) fn has_guardian_role(&self) -> bool {
+        self.roles.contains(&RoleV1::Guardian)
+    }
+}
+
+/// Keeps every role but the guardian roles, which cannot be tied to a guardian record.
+impl From<RoleSetV1> for RoleSet {
+    fn from(set: RoleSetV1) -> Self {
+        let roles = set
+            .roles
+            .into_iter()
+            .filter_map(|role| match role {
+                RoleV1::Admin => Some(Role::Admin),
+                RoleV1::Registrar => Some(Role::Registrar),
+                RoleV1::Teacher { teacher_id } => Some(Role::Teacher { teacher_id }),
+                RoleV1::Student { student_id } => Some(Role::Student { student_id }),
+                RoleV1::Guardian => None,
+            })
+            .collect();
+        RoleSet { roles }
+    }
+}
+
+impl Versioned for RoleSet {
+    const VERSION: u8 = 2;
+    const NAME: &'static str = "role set";
+
+    fn decode_older(version: Option<u8>, bytes: &[u8]) -> Result<Self, candid::Error> {
+        match version {
+            Some(1) => Decode!(bytes, RoleSetV1).map(RoleSet::from),
+            None => Decode!(bytes, RoleSet)
+                .or_else(|_| Self::decode_older(Some(1), bytes))
+                .map_err(|_| unknown_version(Self::NAME, None)),
+            _ => Err(unknown_version(Self::NAME, version)),
+        }
+    }
+}
+
+/// Implements `Versioned` for records still in their first version, which were stored as plain
+/// Candid before envelopes existed.
+macro_rules! first_version {
+    ($($record:ty => $name:literal),* $(,)?) => {
+        $(
+            impl Versioned for $record {
+                const VERSION: u8 = 1;
+                const NAME: &'static str = $name;
+
+                fn decode_older(version: Option<u8>, bytes: &[u8]) -> Result<Self, candid::Error> {
+                    match version {
+                        None => Decode!(bytes, $record),
+                        _ => Err(unknown_version(Self::NAME, version)),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+first_version!(
+    AttendanceRecord => "attendance record",
+    PerformanceRecord => "performance record",
+    ScheduleSlot => "schedule slot",
+    GradingScale => "grading scale",
+    SchoolSettings => "school settings",
+    AuditEntry => "audit entry",
+);
+
+#[cfg(test)]
+mod tests {
+    //! Each fixture mirrors a layout the canister has stored, encoded the way it was stored.
+
+    use super::*;
+    use crate::{
+        AcademicYear, ArchiveInfo, AttendanceStatus, DayOfWeek, GuardianId, LegacyGuardianDetails,
+        LegacyTextDate, Recurrence, Relationship, Role, TimeOfDay, MAX_ASSESSMENT_NAME_LENGTH,
+        MAX_ATTENDANCE_NOTE_LENGTH, MAX_ROLES_PER_PRINCIPAL,
+    };
+    use candid::Principal;
+    use ic_stable_structures::{BoundedStorable, Storable};
+    use std::borrow::Cow;
+
+    fn metadata() -> Metadata {
+        Metadata {
+            created_at: 1_700_000_000_000_000_000,
+            updated_at: 1_700_000_100_000_000_000,
+            updated_by: Principal::management_canister(),
+        }
+    }
+
+    fn date(year: u16, month: u8, day: u8) -> Date {
+        Date { year, month, day }
+    }
+
+    fn assert_default_metadata(metadata: &Metadata) {
+        assert_eq!(metadata.created_at, 0);
+        assert_eq!(metadata.updated_at, 0);
+        assert_eq!(metadata.updated_by, Principal::anonymous());
+    }
+
+    /// Returns the bytes of a hex dump of a record stored by an earlier build of the canister.
+    fn stored(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[derive(CandidType)]
+    struct StudentWithRecordLists {
+        id: u64,
+        name: String,
+        grade_level: u8,
+        enrolled_courses: Vec<u64>,
+        email: String,
+        date_of_birth: String,
+        address: String,
+        guardian_details: String,
+        performance_records: Vec<u64>,
+        attendance_records: Vec<u64>,
+    }
+
+    #[derive(CandidType)]
+    struct StudentWithPerformanceRecords {
+        id: u64,
+        name: String,
+        grade_level: u8,
+        enrolled_courses: Vec<u64>,
+        email: String,
+        date_of_birth: String,
+        address: String,
+        guardian_details: String,
+        performance_records: Vec<u64>,
+    }
+
+    #[derive(CandidType)]
+    struct StudentWithTextDate {
+        id: u64,
+        name: String,
+        grade_level: u8,
+        enrolled_courses: Vec<u64>,
+        email: String,
+        date_of_birth: String,
+        address: String,
+        guardian_details: String,
+    }
+
+    #[derive(CandidType)]
+    struct StudentWithGuardianDetails {
+        id: u64,
+        name: String,
+        grade_level: u8,
+        enrolled_courses: Vec<u64>,
+        email: String,
+        date_of_birth: Date,
+        address: String,
+        guardian_details: String,
+    }
+
+    #[derive(CandidType)]
+    struct StudentWithoutMetadata {
+        id: u64,
+        name: String,
+        grade_level: u8,
+        enrolled_courses: Vec<u64>,
+        email: String,
+        date_of_birth: Date,
+        address: String,
+    }
+
+    fn assert_student(student: &Student, date_of_birth: Date) {
+        assert_eq!(u64::from(student.id), 7);
+        assert_eq!(student.name, "Ada");
+        assert_eq!(student.grade_level, 5);
+        assert_eq!(student.enrolled_courses, vec![CourseId::from(3), CourseId::from(4)]);
+        assert_eq!(student.email, "ada@example.com");
+        assert_eq!(student.date_of_birth, date_of_birth);
+        assert_eq!(student.address, "1 Main St");
+    }
+
+    #[test]
+    fn decodes_student_with_record_lists() {
+        let bytes = Encode!(&StudentWithRecordLists {
+            id: 7,
+            name: "Ada".to_string(),
+            grade_level: 5,
+            enrolled_courses: vec![3, 4],
+            email: "ada@example.com".to_string(),
+            date_of_birth: "2014-02-28".to_string(),
+            address: "1 Main St".to_string(),
+            guardian_details: "Grace, 555-0100".to_string(),
+            performance_records: vec![11],
+            attendance_records: vec![12, 13],
+        })
+        .unwrap();
+
+        let student: Student = decode(&bytes);
+        assert_student(&student, date(2014, 2, 28));
+        assert_default_metadata(&student.metadata);
+    }
+
+    #[test]
+    fn decodes_student_with_performance_records() {
+        let bytes = Encode!(&StudentWithPerformanceRecords {
+            id: 7,
+            name: "Ada".to_string(),
+            grade_level: 5,
+            enrolled_courses: vec![3, 4],
+            email: "ada@example.com".to_string(),
+            date_of_birth: "2014-02-28".to_string(),
+            address: "1 Main St".to_string(),
+            guardian_details: String::new(),
+            performance_records: vec![11],
+        })
+        .unwrap();
+
+        assert_student(&decode(&bytes), date(2014, 2, 28));
+    }
+
+    #[test]
+    fn decodes_student_stored_without_attendance_records() {
+        // Stored once the attendance records had moved to their own map.
+        let bytes = stored(concat!(
+            "4449444c026c09dbb70178feaf818e0401cbe4fdc70471b3c087e006019c9ebbfe0671dcb9d8b0097bb4e3ade8097188",
+            "ccc2fe0b7194b29eee0f716d7801000700000000000000020300000000000000040000000000000003416461010b0000",
+            "00000000000f616461406578616d706c652e636f6d050931204d61696e2053740a323031342d30322d32380f47726163",
+            "652c203535352d30313030",
+        ));
+
+        let student: Student = decode(&bytes);
+        assert_student(&student, date(2014, 2, 28));
+        assert_default_metadata(&student.metadata);
+    }
+
+    #[test]
+    fn decodes_student_with_text_date() {
+        let bytes = Encode!(&StudentWithTextDate {
+            id: 7,
+            name: "Ada".to_string(),
+            grade_level: 5,
+            enrolled_courses: vec![3, 4],
+            email: "ada@example.com".to_string(),
+            date_of_birth: "2014-02-28".to_string(),
+            address: "1 Main St".to_string(),
+            guardian_details: String::new(),
+        })
+        .unwrap();
+
+        assert_student(&decode(&bytes), date(2014, 2, 28));
+    }
+
+    #[test]
+    fn invalid_text_date_becomes_default_date() {
+        let bytes = Encode!(&StudentWithTextDate {
+            id: 7,
+            name: "Ada".to_string(),
+            grade_level: 5,
+            enrolled_courses: vec![3, 4],
+            email: "ada@example.com".to_string(),
+            date_of_birth: "28/02/2014".to_string(),
+            address: "1 Main St".to_string(),
+            guardian_details: String::new(),
+        })
+        .unwrap();
+
+        assert_student(&decode(&bytes), Date::default());
+    }
+
+    #[test]
+    fn decodes_student_with_guardian_details() {
+        let bytes = Encode!(&StudentWithGuardianDetails {
+            id: 7,
+            name: "Ada".to_string(),
+            grade_level: 5,
+            enrolled_courses: vec![3, 4],
+            email: "ada@example.com".to_string(),
+            date_of_birth: date(2014, 2, 28),
+            address: "1 Main St".to_string(),
+            guardian_details: "Grace, 555-0100".to_string(),
+        })
+        .unwrap();
+
+        let student: Student = decode(&bytes);
+        assert_student(&student, date(2014, 2, 28));
+        assert_default_metadata(&student.metadata);
+    }
+
+    #[test]
+    fn decodes_student_stored_with_calendar_date() {
+        // Stored once dates of birth had become calendar dates.
+        let bytes = stored(concat!(
+            "4449444c036c08dbb70178feaf818e0401cbe4fdc704719c9ebbfe0671dcb9d8b0097bb4e3ade8097188ccc2",
+            "fe0b0294b29eee0f716d786c03dcecb0027bc08eff627bbd939f82057a010007000000000000000203000000",
+            "000000000400000000000000034164610f616461406578616d706c652e636f6d050931204d61696e2053741c",
+            "02de070f47726163652c203535352d30313030",
+        ));
+
+        let student: Student = decode(&bytes);
+        assert_student(&student, date(2014, 2, 28));
+        assert_default_metadata(&student.metadata);
+    }
+
+    #[test]
+    fn decodes_student_stored_with_metadata() {
+        // Stored once every record carried metadata.
+        let bytes = stored(concat!(
+            "4449444c046c08dbb70178efcee7800401feaf818e0402cbe4fdc704719c9ebbfe0671dcb9d8b0097bb4e3ad",
+            "e8097188ccc2fe0b036c03b7fff58101789b81f6810168aaacd9d006786d786c03dcecb0027bc08eff627bbd",
+            "939f82057a0100070000000000000000e8a07e159d9717010000002a36fe9c97170203000000000000000400",
+            "000000000000034164610f616461406578616d706c652e636f6d050931204d61696e2053741c02de07",
+        ));
+
+        let student: Student = decode(&bytes);
+        assert_student(&student, date(2014, 2, 28));
+        assert_eq!(student.metadata.created_at, metadata().created_at);
+    }
+
+    #[test]
+    fn decodes_student_without_metadata() {
+        let bytes = Encode!(&StudentWithoutMetadata {
+            id: 7,
+            name: "Ada".to_string(),
+            grade_level: 5,
+            enrolled_courses: vec![3, 4],
+            email: "ada@example.com".to_string(),
+            date_of_birth: date(2014, 2, 28),
+            address: "1 Main St".to_string(),
+        })
+        .unwrap();
+
+        let student: Student = decode(&bytes);
+        assert_student(&student, date(2014, 2, 28));
+        assert_default_metadata(&student.metadata);
+    }
+
+    fn current_student() -> Student {
+        Student {
+            id: StudentId::from(7),
+            name: "Ada".to_string(),
+            grade_level: 5,
+            enrolled_courses: vec![CourseId::from(3), CourseId::from(4)],
+            email: "ada@example.com".to_string(),
+            date_of_birth: date(2014, 2, 28),
+            address: "1 Main St".to_string(),
+            metadata: metadata(),
+        }
+    }
+
+    #[test]
+    fn decodes_unversioned_student_with_metadata() {
+        let student: Student = decode(&Encode!(&current_student()).unwrap());
+        assert_student(&student, date(2014, 2, 28));
+        assert_eq!(student.metadata.created_at, metadata().created_at);
+    }
+
+    #[test]
+    fn round_trips_student_in_envelope() {
+        let bytes = encode(&current_student());
+        assert!(bytes.starts_with(ENVELOPE_MAGIC));
+        assert_eq!(bytes[ENVELOPE_MAGIC.len()], Student::VERSION);
+
+        let student: Student = decode(&bytes);
+        assert_student(&student, date(2014, 2, 28));
+        assert_eq!(student.metadata.updated_at, metadata().updated_at);
+        assert!(bytes.len() <= <Student as BoundedStorable>::MAX_SIZE as usize);
+    }
+
+    #[test]
+    fn migrates_enveloped_older_student_versions() {
+        let first = envelope(
+            1,
+            Encode!(&StudentV1 {
+                id: StudentId::from(7),
+                name: "Ada".to_string(),
+                grade_level: 5,
+                enrolled_courses: vec![CourseId::from(3), CourseId::from(4)],
+                email: "ada@example.com".to_string(),
+                date_of_birth: "2014-02-28".to_string(),
+                address: "1 Main St".to_string(),
+            })
+            .unwrap(),
+        );
+        assert_student(&decode(&first), date(2014, 2, 28));
+
+        let second = envelope(
+            2,
+            Encode!(&StudentV2 {
+                id: StudentId::from(7),
+                name: "Ada".to_string(),
+                grade_level: 5,
+                enrolled_courses: vec![CourseId::from(3), CourseId::from(4)],
+                email: "ada@example.com".to_string(),
+                date_of_birth: date(2014, 2, 28),
+                address: "1 Main St".to_string(),
+            })
+            .unwrap(),
+        );
+        assert_student(&decode(&second), date(2014, 2, 28));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown student version 9")]
+    fn rejects_newer_student_version() {
+        let mut bytes = encode(&current_student());
+        bytes[ENVELOPE_MAGIC.len()] = 9;
+        let _: Student = decode(&bytes);
+    }
+
+    #[test]
+    fn reads_legacy_guardian_details_only_from_unversioned_students() {
+        let legacy = Encode!(&StudentWithGuardianDetails {
+            id: 7,
+            name: "Ada".to_string(),
+            grade_level: 5,
+            enrolled_courses: vec![],
+            email: "ada@example.com".to_string(),
+            date_of_birth: date(2014, 2, 28),
+            address: "1 Main St".to_string(),
+            guardian_details: "Grace, 555-0100".to_string(),
+        })
+        .unwrap();
+        let details = LegacyGuardianDetails::from_bytes(Cow::Owned(legacy)).guardian_details;
+        assert_eq!(details.as_deref(), Some("Grace, 555-0100"));
+
+        let enveloped = encode(&current_student());
+        assert!(LegacyGuardianDetails::from_bytes(Cow::Owned(enveloped)).guardian_details.is_none());
+    }
+
+    #[derive(CandidType)]
+    struct TeacherWithTextDate {
+        id: u64,
+        name: String,
+        subject_area: String,
+        assigned_courses: Vec<u64>,
+        email: String,
+        qualifications: String,
+        employment_date: String,
+        address: String,
+        schedule: Vec<u64>,
+    }
+
+    #[derive(CandidType)]
+    struct TeacherWithoutMetadata {
+        id: u64,
+        name: String,
+        subject_area: String,
+        assigned_courses: Vec<u64>,
+        email: String,
+        qualifications: String,
+        employment_date: Date,
+        address: String,
+        schedule: Vec<u64>,
+    }
+
+    fn assert_teacher(teacher: &Teacher) {
+        assert_eq!(u64::from(teacher.id), 2);
+        assert_eq!(teacher.name, "Alan");
+        assert_eq!(teacher.subject_area, "Mathematics");
+        assert_eq!(teacher.assigned_courses, vec![CourseId::from(3)]);
+        assert_eq!(teacher.email, "alan@example.com");
+        assert_eq!(teacher.qualifications, "PhD");
+        assert_eq!(teacher.employment_date, date(2010, 9, 1));
+        assert_eq!(teacher.address, "2 High St");
+        assert_eq!(teacher.schedule, vec![21, 22]);
+    }
+
+    #[test]
+    fn decodes_teacher_with_text_date() {
+        let bytes = Encode!(&TeacherWithTextDate {
+            id: 2,
+            name: "Alan".to_string(),
+            subject_area: "Mathematics".to_string(),
+            assigned_courses: vec![3],
+            email: "alan@example.com".to_string(),
+            qualifications: "PhD".to_string(),
+            employment_date: "2010-09-01".to_string(),
+            address: "2 High St".to_string(),
+            schedule: vec![21, 22],
+        })
+        .unwrap();
+
+        let teacher: Teacher = decode(&bytes);
+        assert_teacher(&teacher);
+        assert_default_metadata(&teacher.metadata);
+    }
+
+    #[test]
+    fn reads_legacy_text_dates_only_from_unversioned_records() {
+        let student = Encode!(&StudentWithTextDate {
+            id: 7,
+            name: "Ada".to_string(),
+            grade_level: 5,
+            enrolled_courses: vec![3, 4],
+            email: "ada@example.com".to_string(),
+            date_of_birth: "28/02/2014".to_string(),
+            address: "1 Main St".to_string(),
+            guardian_details: String::new(),
+        })
+        .unwrap();
+        let text = LegacyTextDate::<Student>::from_bytes(Cow::Owned(student)).text();
+        assert_eq!(text.as_deref(), Some("28/02/2014"));
+
+        let teacher = Encode!(&TeacherWithTextDate {
+            id: 2,
+            name: "Alan".to_string(),
+            subject_area: "Mathematics".to_string(),
+            assigned_courses: vec![3],
+            email: "alan@example.com".to_string(),
+            qualifications: "PhD".to_string(),
+            employment_date: "September 2010".to_string(),
+            address: "2 High St".to_string(),
+            schedule: vec![21, 22],
+        })
+        .unwrap();
+        let text = LegacyTextDate::<Teacher>::from_bytes(Cow::Owned(teacher)).text();
+        assert_eq!(text.as_deref(), Some("September 2010"));
+
+        let calendar_date = Encode!(&current_student()).unwrap();
+        assert!(LegacyTextDate::<Student>::from_bytes(Cow::Owned(calendar_date)).text().is_none());
+        let enveloped = encode(&current_student());
+        assert!(LegacyTextDate::<Student>::from_bytes(Cow::Owned(enveloped)).text().is_none());
+    }
+
+    #[test]
+    fn decodes_teacher_without_metadata() {
+        let bytes = Encode!(&TeacherWithoutMetadata {
+            id: 2,
+            name: "Alan".to_string(),
+            subject_area: "Mathematics".to_string(),
+            assigned_courses: vec![3],
+            email: "alan@example.com".to_string(),
+            qualifications: "PhD".to_string(),
+            employment_date: date(2010, 9, 1),
+            address: "2 High St".to_string(),
+            schedule: vec![21, 22],
+        })
+        .unwrap();
+
+        let teacher: Teacher = decode(&bytes);
+        assert_teacher(&teacher);
+        assert_default_metadata(&teacher.metadata);
+    }
+
+    #[test]
+    fn decodes_teacher_stored_with_calendar_date() {
+        // Stored once employment dates had become calendar dates.
+        let bytes = stored(concat!(
+            "4449444c036c09dbb70178e1cecbb30401cbe4fdc70471c0acf0ed0571e4f39ba706719c9ebbfe0671b4e3ad",
+            "e80971d794fbab0c0287cecfb80e026c03dcecb0027bc08eff627bbd939f82057a6d78010002000000000000",
+            "000109da0704416c616e0b4d617468656d61746963730350684410616c616e406578616d706c652e636f6d09",
+            "3220486967682053740215000000000000001600000000000000010300000000000000",
+        ));
+
+        let teacher: Teacher = decode(&bytes);
+        assert_teacher(&teacher);
+        assert_default_metadata(&teacher.metadata);
+    }
+
+    #[test]
+    fn decodes_teacher_stored_with_metadata() {
+        // Stored once every record carried metadata.
+        let bytes = stored(concat!(
+            "4449444c046c0adbb70178efcee7800401e1cecbb30402cbe4fdc70471c0acf0ed0571e4f39ba706719c9ebb",
+            "fe0671b4e3ade80971d794fbab0c0387cecfb80e036c03b7fff58101789b81f6810168aaacd9d006786c03dc",
+            "ecb0027bc08eff627bbd939f82057a6d780100020000000000000000e8a07e159d9717010000002a36fe9c97",
+            "170109da0704416c616e0b4d617468656d61746963730350684410616c616e406578616d706c652e636f6d09",
+            "3220486967682053740215000000000000001600000000000000010300000000000000",
+        ));
+
+        let teacher: Teacher = decode(&bytes);
+        assert_teacher(&teacher);
+        assert_eq!(teacher.metadata.created_at, metadata().created_at);
+    }
+
+    #[test]
+    fn decodes_teacher_with_metadata_with_and_without_envelope() {
+        let current = Teacher {
+            id: TeacherId::from(2),
+            name: "Alan".to_string(),
+            subject_area: "Mathematics".to_string(),
+            assigned_courses: vec![CourseId::from(3)],
+            email: "alan@example.com".to_string(),
+            qualifications: "PhD".to_string(),
+            employment_date: date(2010, 9, 1),
+            address: "2 High St".to_string(),
+            schedule: vec![21, 22],
+            metadata: metadata(),
+        };
+
+        for bytes in [Encode!(&current).unwrap(), encode(&current)] {
+            let teacher: Teacher = decode(&bytes);
+            assert_teacher(&teacher);
+            assert_eq!(teacher.metadata.created_at, metadata().created_at);
+        }
+    }
+
+    #[derive(CandidType)]
+    struct CourseWithTextSchedule {
+        id: u64,
+        name: String,
+        description: String,
+        teacher_id: u64,
+        student_ids: Vec<u64>,
+        schedule: String,
+        syllabus: String,
+        course_materials: Vec<String>,
+    }
+
+    #[derive(CandidType)]
+    struct CourseWithoutMetadata {
+        id: u64,
+        name: String,
+        description: String,
+        teacher_id: u64,
+        student_ids: Vec<u64>,
+        syllabus: String,
+        course_materials: Vec<String>,
+    }
+
+    #[derive(CandidType, Clone)]
+    struct CourseWithRequiredTeacher {
+        id: u64,
+        name: String,
+        description: String,
+        teacher_id: u64,
+        student_ids: Vec<u64>,
+        syllabus: String,
+        course_materials: Vec<String>,
+        metadata: Metadata,
+    }
+
+    fn assert_course(course: &Course, teacher_id: Option<u64>) {
+        assert_eq!(u64::from(course.id), 3);
+        assert_eq!(course.name, "Algebra");
+        assert_eq!(course.description, "Linear equations");
+        assert_eq!(course.teacher_id, teacher_id.map(TeacherId::from));
+        assert_eq!(course.student_ids, vec![StudentId::from(7)]);
+        assert_eq!(course.syllabus, "Chapters 1-4");
+        assert_eq!(course.course_materials, vec!["https://example.com/algebra".to_string()]);
+    }
+
+    #[test]
+    fn decodes_course_with_text_schedule() {
+        let bytes = Encode!(&CourseWithTextSchedule {
+            id: 3,
+            name: "Algebra".to_string(),
+            description: "Linear equations".to_string(),
+            teacher_id: 2,
+            student_ids: vec![7],
+            schedule: "Mondays 9:00".to_string(),
+            syllabus: "Chapters 1-4".to_string(),
+            course_materials: vec!["https://example.com/algebra".to_string()],
+        })
+        .unwrap();
+
+        let course: Course = decode(&bytes);
+        assert_course(&course, Some(2));
+        assert_default_metadata(&course.metadata);
+    }
+
+    #[test]
+    fn decodes_course_without_metadata() {
+        let bytes = Encode!(&CourseWithoutMetadata {
+            id: 3,
+            name: "Algebra".to_string(),
+            description: "Linear equations".to_string(),
+            teacher_id: 0,
+            student_ids: vec![7],
+            syllabus: "Chapters 1-4".to_string(),
+            course_materials: vec!["https://example.com/algebra".to_string()],
+        })
+        .unwrap();
+
+        let course: Course = decode(&bytes);
+        assert_course(&course, None);
+        assert_default_metadata(&course.metadata);
+    }
+
+    #[test]
+    fn decodes_course_with_required_teacher() {
+        let fixture = CourseWithRequiredTeacher {
+            id: 3,
+            name: "Algebra".to_string(),
+            description: "Linear equations".to_string(),
+            teacher_id: 2,
+            student_ids: vec![7],
+            syllabus: "Chapters 1-4".to_string(),
+            course_materials: vec!["https://example.com/algebra".to_string()],
+            metadata: metadata(),
+        };
+        let course: Course = decode(&Encode!(&fixture).unwrap());
+        assert_course(&course, Some(2));
+        assert_eq!(course.metadata.created_at, metadata().created_at);
+
+        let without_teacher = CourseWithRequiredTeacher {
+            teacher_id: 0,
+            ..fixture
+        };
+        assert_course(&decode(&Encode!(&without_teacher).unwrap()), None);
+    }
+
+    #[test]
+    fn decodes_course_stored_with_metadata() {
+        // Stored once every record carried metadata, while every course named a teacher, 0
+        // standing for none.
+        let with_teacher = stored(concat!(
+            "4449444c046c08dbb70178c8d4d730019896ffa00278f4de88940302efcee7800403cbe4fdc70471fc91f4f8",
+            "0571a5f2a1bc0f716d716d786c03b7fff58101789b81f6810168aaacd9d0067801000300000000000000011b",
+            "68747470733a2f2f6578616d706c652e636f6d2f616c67656272610200000000000000010700000000000000",
+            "00e8a07e159d9717010000002a36fe9c971707416c6765627261104c696e656172206571756174696f6e730c",
+            "436861707465727320312d34",
+        ));
+        let without_teacher = stored(concat!(
+            "4449444c046c08dbb70178c8d4d730019896ffa00278f4de88940302efcee7800403cbe4fdc70471fc91f4f8",
+            "0571a5f2a1bc0f716d716d786c03b7fff58101789b81f6810168aaacd9d0067801000300000000000000011b",
+            "68747470733a2f2f6578616d706c652e636f6d2f616c67656272610000000000000000010700000000000000",
+            "00e8a07e159d9717010000002a36fe9c971707416c6765627261104c696e656172206571756174696f6e730c",
+            "436861707465727320312d34",
+        ));
+
+        for (bytes, expected) in [(with_teacher, Some(2)), (without_teacher, None)] {
+            let course: Course = decode(&bytes);
+            assert_course(&course, expected);
+            assert_eq!(course.metadata.created_at, metadata().created_at);
+        }
+    }
+
+    #[test]
+    fn decodes_course_with_optional_teacher_with_and_without_envelope() {
+        for teacher_id in [Some(2), None] {
+            let current = Course {
+                id: CourseId::from(3),
+                name: "Algebra".to_string(),
+                description: "Linear equations".to_string(),
+                teacher_id: teacher_id.map(TeacherId::from),
+                student_ids: vec![StudentId::from(7)],
+                syllabus: "Chapters 1-4".to_string(),
+                course_materials: vec!["https://example.com/algebra".to_string()],
+                metadata: metadata(),
+            };
+            for bytes in [Encode!(&current).unwrap(), encode(&current)] {
+                assert_course(&decode(&bytes), teacher_id);
+            }
+        }
+    }
+
+    #[test]
+    fn decodes_course_stored_with_optional_teacher() {
+        // Stored once the teacher of a course had become optional, before envelopes.
+        let with_teacher = stored(concat!(
+            "4449444c056c08dbb70178c8d4d730019896ffa00202f4de88940303efcee7800404cbe4fdc70471fc91f4f8",
+            "0571a5f2a1bc0f716d716e786d786c03b7fff58101789b81f6810168aaacd9d0067801000300000000000000",
+            "011b68747470733a2f2f6578616d706c652e636f6d2f616c6765627261010200000000000000010700000000",
+            "00000000e8a07e159d9717010000002a36fe9c971707416c6765627261104c696e656172206571756174696f",
+            "6e730c436861707465727320312d34",
+        ));
+        let without_teacher = stored(concat!(
+            "4449444c056c08dbb70178c8d4d730019896ffa00202f4de88940303efcee7800404cbe4fdc70471fc91f4f8",
+            "0571a5f2a1bc0f716d716e786d786c03b7fff58101789b81f6810168aaacd9d0067801000300000000000000",
+            "011b68747470733a2f2f6578616d706c652e636f6d2f616c67656272610001070000000000000000e8a07e15",
+            "9d9717010000002a36fe9c971707416c6765627261104c696e656172206571756174696f6e730c4368617074",
+            "65727320312d34",
+        ));
+
+        for (bytes, expected) in [(with_teacher, Some(2)), (without_teacher, None)] {
+            let course: Course = decode(&bytes);
+            assert_course(&course, expected);
+            assert_eq!(course.metadata.created_at, metadata().created_at);
+        }
+    }
+
+    #[derive(CandidType)]
+    struct ClassroomWithoutMetadata {
+        id: u64,
+        name: String,
+        location: String,
+        capacity: u32,
+        current_course_id: u64,
+        equipment: Vec<String>,
+    }
+
+    #[derive(CandidType)]
+    struct ClassroomWithRequiredCourse {
+        id: u64,
+        name: String,
+        location: String,
+        capacity: u32,
+        current_course_id: u64,
+        equipment: Vec<String>,
+        metadata: Metadata,
+    }
+
+    fn assert_classroom(classroom: &Classroom, current_course_id: Option<u64>) {
+        assert_eq!(u64::from(classroom.id), 4);
+        assert_eq!(classroom.name, "Room 101");
+        assert_eq!(classroom.location, "First floor");
+        assert_eq!(classroom.capacity, 30);
+        assert_eq!(classroom.current_course_id, current_course_id.map(CourseId::from));
+        assert_eq!(classroom.equipment, vec!["Projector".to_string()]);
+    }
+
+    #[test]
+    fn decodes_classroom_without_metadata() {
+        for (stored, expected) in [(3, Some(3)), (0, None)] {
+            let bytes = Encode!(&ClassroomWithoutMetadata {
+                id: 4,
+                name: "Room 101".to_string(),
+                location: "First floor".to_string(),
+                capacity: 30,
+                current_course_id: stored,
+                equipment: vec!["Projector".to_string()],
+            })
+            .unwrap();
+
+            let classroom: Classroom = decode(&bytes);
+            assert_classroom(&classroom, expected);
+            assert_default_metadata(&classroom.metadata);
+        }
+    }
+
+    #[test]
+    fn decodes_classroom_with_required_course() {
+        for (stored, expected) in [(3, Some(3)), (0, None)] {
+            let bytes = Encode!(&ClassroomWithRequiredCourse {
+                id: 4,
+                name: "Room 101".to_string(),
+                location: "First floor".to_string(),
+                capacity: 30,
+                current_course_id: stored,
+                equipment: vec!["Projector".to_string()],
+                metadata: metadata(),
+            })
+            .unwrap();
+
+            let classroom: Classroom = decode(&bytes);
+            assert_classroom(&classroom, expected);
+            assert_eq!(classroom.metadata.created_at, metadata().created_at);
+        }
+    }
+
+    #[test]
+    fn decodes_classroom_stored_with_metadata() {
+        // Stored once every record carried metadata, while every classroom named a current
+        // course, 0 standing for none.
+        let with_course = stored(concat!(
+            "4449444c036c07dbb70178cee894d40301efcee7800402cbe4fdc70471b9da80e40a78ba82ec9d0c79b5dc99",
+            "aa0e716d716c03b7fff58101789b81f6810168aaacd9d0067801000400000000000000010950726f6a656374",
+            "6f7200e8a07e159d9717010000002a36fe9c971708526f6f6d2031303103000000000000001e0000000b4669",
+            "72737420666c6f6f72",
+        ));
+        let without_course = stored(concat!(
+            "4449444c036c07dbb70178cee894d40301efcee7800402cbe4fdc70471b9da80e40a78ba82ec9d0c79b5dc99",
+            "aa0e716d716c03b7fff58101789b81f6810168aaacd9d0067801000400000000000000010950726f6a656374",
+            "6f7200e8a07e159d9717010000002a36fe9c971708526f6f6d2031303100000000000000001e0000000b4669",
+            "72737420666c6f6f72",
+        ));
+
+        for (bytes, expected) in [(with_course, Some(3)), (without_course, None)] {
+            let classroom: Classroom = decode(&bytes);
+            assert_classroom(&classroom, expected);
+            assert_eq!(classroom.metadata.created_at, metadata().created_at);
+        }
+    }
+
+    #[test]
+    fn decodes_classroom_with_optional_course_with_and_without_envelope() {
+        for current_course_id in [Some(3), None] {
+            let current = Classroom {
+                id: ClassroomId::from(4),
+                name: "Room 101".to_string(),
+                location: "First floor".to_string(),
+                capacity: 30,
+                current_course_id: current_course_id.map(CourseId::from),
+                equipment: vec!["Projector".to_string()],
+                metadata: metadata(),
+            };
+            for bytes in [Encode!(&current).unwrap(), encode(&current)] {
+                assert_classroom(&decode(&bytes), current_course_id);
+            }
+        }
+    }
+
+    #[test]
+    fn decodes_classroom_stored_with_optional_course() {
+        // Stored once the current course of a classroom had become optional, before envelopes.
+        let with_course = stored(concat!(
+            "4449444c046c07dbb70178cee894d40301efcee7800402cbe4fdc70471b9da80e40a03ba82ec9d0c79b5dc99",
+            "aa0e716d716c03b7fff58101789b81f6810168aaacd9d006786e7801000400000000000000010950726f6a65",
+            "63746f7200e8a07e159d9717010000002a36fe9c971708526f6f6d203130310103000000000000001e000000",
+            "0b466972737420666c6f6f72",
+        ));
+        let without_course = stored(concat!(
+            "4449444c046c07dbb70178cee894d40301efcee7800402cbe4fdc70471b9da80e40a03ba82ec9d0c79b5dc99",
+            "aa0e716d716c03b7fff58101789b81f6810168aaacd9d006786e7801000400000000000000010950726f6a65",
+            "63746f7200e8a07e159d9717010000002a36fe9c971708526f6f6d20313031001e0000000b46697273742066",
+            "6c6f6f72",
+        ));
+
+        for (bytes, expected) in [(with_course, Some(3)), (without_course, None)] {
+            let classroom: Classroom = decode(&bytes);
+            assert_classroom(&classroom, expected);
+            assert_eq!(classroom.metadata.created_at, metadata().created_at);
+        }
+    }
+
+    fn guardian() -> Guardian {
+        Guardian {
+            id: crate::GuardianId::from(5),
+            name: "Grace".to_string(),
+            relationship: Relationship::Parent,
+            phone: "555-0100".to_string(),
+            email: "grace@example.com".to_string(),
+            principal: Some(Principal::management_canister()),
+            has_custody: true,
+            can_pick_up: true,
+            notes: String::new(),
+            metadata: metadata(),
+        }
+    }
+
+    #[test]
+    fn decodes_guardian_with_and_without_envelope() {
+        for bytes in [Encode!(&guardian()).unwrap(), encode(&guardian())] {
+            let decoded: Guardian = decode(&bytes);
+            assert_eq!(decoded.name, "Grace");
+            assert_eq!(decoded.principal, Some(Principal::management_canister()));
+        }
+    }
+
+    #[test]
+    fn decodes_guardian_without_metadata() {
+        let plain = stored(concat!(
+            "4449444c036c09dbb70178cae7e3217eae9db1900101f8a8bec80202cbe4fdc704719c9ebbfe0671acdd86",
+            "9b097ec1b8abfe0971ee86cf8f0c716e686b0596b7fd5e7fcaf2c990027fe2a999df027fb0ad8fcd0c7f8a",
+            "8eebb10e7f010005000000000000000101010001054772616365116772616365406578616d706c652e636f",
+            "6d0100083535352d30313030",
+        ));
+        for bytes in [plain.clone(), envelope(1, plain)] {
+            let guardian: Guardian = decode(&bytes);
+            assert_eq!(guardian.id, GuardianId::from(5));
+            assert_eq!(guardian.name, "Grace");
+            assert_eq!(guardian.relationship, Relationship::Parent);
+            assert_eq!(guardian.phone, "555-0100");
+            assert_eq!(guardian.email, "grace@example.com");
+            assert_eq!(guardian.principal, Some(Principal::management_canister()));
+            assert!(guardian.has_custody && guardian.can_pick_up);
+            assert!(guardian.notes.is_empty());
+            assert_default_metadata(&guardian.metadata);
+        }
+    }
+
+    #[test]
+    fn decodes_role_sets_without_their_unnamed_guardian_roles() {
+        // Roles of teacher 2 and of an unnamed guardian, then of an unnamed guardian alone.
+        let teacher_and_guardian = stored(concat!(
+            "4449444c056c01bdb8fcea0e016d026b0582a0d09502038dbebae7057f9ba98ee90704f1cb89e8087fefb8",
+            "e0fb0a7f6c019896ffa002786c019fd8f1f2057801000200020000000000000003",
+        ));
+        let guardian_only = stored(concat!(
+            "4449444c056c01bdb8fcea0e016d026b0582a0d09502038dbebae7057f9ba98ee90704f1cb89e8087fefb8",
+            "e0fb0a7f6c019896ffa002786c019fd8f1f2057801000103",
+        ));
+        for bytes in [teacher_and_guardian.clone(), envelope(1, teacher_and_guardian)] {
+            let roles: RoleSet = decode(&bytes);
+            assert_eq!(roles.roles, vec![Role::Teacher { teacher_id: TeacherId::from(2) }]);
+        }
+        for bytes in [guardian_only.clone(), envelope(1, guardian_only)] {
+            assert!(decode::<RoleSet>(&bytes).roles.is_empty());
+        }
+    }
+
+    #[test]
+    fn legacy_views_read_nothing_from_undecodable_records() {
+        let garbage = b"DIDL\x00\x01\x7f\x00".to_vec();
+        assert!(LegacyGuardianDetails::from_bytes(Cow::Owned(garbage.clone())).guardian_details.is_none());
+        assert!(LegacyTextDate::<Student>::from_bytes(Cow::Owned(garbage.clone())).text().is_none());
+        assert!(RoleSetV1::from_bytes(Cow::Owned(garbage)).roles.is_empty());
+        assert!(RoleSetV1::from_bytes(Cow::Owned(encode(&role_set()))).roles.is_empty());
+    }
+
+    /// Asserts that a record decodes to itself from plain Candid, as stored before envelopes, and
+    /// from its envelope.
+    fn assert_decodes_with_and_without_envelope<T: Versioned>(record: &T) {
+        let plain = Encode!(record).unwrap();
+        for bytes in [plain.clone(), encode(record)] {
+            assert_eq!(Encode!(&decode::<T>(&bytes)).unwrap(), plain);
+        }
+    }
+
+    /// Returns the largest attendance record the endpoints accept.
+    fn attendance_record() -> AttendanceRecord {
+        AttendanceRecord {
+            id: u64::MAX,
+            student_id: StudentId::from(u64::MAX),
+            course_id: CourseId::from(u64::MAX),
+            session_date: date(2025, 9, 1),
+            status: AttendanceStatus::Excused,
+            note: "n".repeat(MAX_ATTENDANCE_NOTE_LENGTH),
+            recorded_by: Principal::from_slice(&[0xff; 29]),
+            recorded_at: u64::MAX,
+        }
+    }
+
+    /// Returns the largest performance record the endpoints accept.
+    fn performance_record() -> PerformanceRecord {
+        PerformanceRecord {
+            id: u64::MAX,
+            student_id: StudentId::from(u64::MAX),
+            course_id: CourseId::from(u64::MAX),
+            assessment_name: "a".repeat(MAX_ASSESSMENT_NAME_LENGTH),
+            score: 95.5,
+            max_score: 100.0,
+            weight: 0.25,
+            graded_by: TeacherId::from(u64::MAX),
+            graded_at: u64::MAX,
+        }
+    }
+
+    /// Returns the largest schedule slot the endpoints accept.
+    fn schedule_slot() -> ScheduleSlot {
+        ScheduleSlot {
+            id: u64::MAX,
+            course_id: CourseId::from(u64::MAX),
+            day_of_week: DayOfWeek::Wednesday,
+            start_time: TimeOfDay { hour: 9, minute: 0 },
+            end_time: TimeOfDay { hour: 10, minute: 30 },
+            classroom_id: ClassroomId::from(u64::MAX),
+            recurrence: Recurrence::Fortnightly,
+            effective_from: date(2025, 9, 1),
+            effective_until: Some(date(2026, 6, 30)),
+        }
+    }
+
+    /// Returns the largest role set the endpoints accept.
+    fn role_set() -> RoleSet {
+        RoleSet {
+            roles: vec![
+                Role::Guardian {
+                    guardian_id: GuardianId::from(u64::MAX)
+                };
+                MAX_ROLES_PER_PRINCIPAL
+            ],
+        }
+    }
+
+    #[test]
+    fn decodes_first_version_records_with_and_without_envelope() {
+        assert_decodes_with_and_without_envelope(&attendance_record());
+        assert_decodes_with_and_without_envelope(&performance_record());
+        assert_decodes_with_and_without_envelope(&schedule_slot());
+        assert_decodes_with_and_without_envelope(&role_set());
+        assert_decodes_with_and_without_envelope(&GradingScale::default());
+        assert_decodes_with_and_without_envelope(&SchoolSettings {
+            school_name: "Northside".to_string(),
+            timezone: "Europe/Berlin".to_string(),
+            academic_year: AcademicYear {
+                start: date(2025, 9, 1),
+                end: date(2026, 6, 30),
+            },
+        });
+        assert_decodes_with_and_without_envelope(&AuditEntry {
+            id: 12,
+            caller: Principal::management_canister(),
+            timestamp: 1_700_000_000_000_000_000,
+            endpoint: "add_guardian".to_string(),
+            entity: "Guardian".to_string(),
+            entity_id: Some(5),
+            before: None,
+            after: Some("{}".to_string()),
+        });
+    }
+
+    #[test]
+    fn enveloped_records_fit_their_maximum_size() {
+        assert!(encode(&attendance_record()).len() <= AttendanceRecord::MAX_SIZE as usize);
+        assert!(encode(&performance_record()).len() <= PerformanceRecord::MAX_SIZE as usize);
+        assert!(encode(&schedule_slot()).len() <= ScheduleSlot::MAX_SIZE as usize);
+        assert!(encode(&role_set()).len() <= RoleSet::MAX_SIZE as usize);
+    }
+
+    fn archive() -> ArchiveInfo {
+        ArchiveInfo {
+            reason: "Moved away".to_string(),
+            archived_by: Principal::management_canister(),
+            archived_at: 1_700_000_200_000_000_000,
+        }
+    }
+
+    #[derive(CandidType)]
+    struct ArchivedCourseWithRequiredTeacher {
+        record: CourseWithRequiredTeacher,
+        archive: ArchiveInfo,
+    }
+
+    #[derive(CandidType)]
+    struct ArchivedClassroomWithRequiredCourse {
+        record: ClassroomWithRequiredCourse,
+        archive: ArchiveInfo,
+    }
+
+    #[test]
+    fn decodes_unversioned_archives() {
+        let student = Encode!(&Archived {
+            record: current_student(),
+            archive: archive(),
+        })
+        .unwrap();
+        let archived: Archived<Student> = decode_archived(&student);
+        assert_student(&archived.record, date(2014, 2, 28));
+        assert_eq!(archived.archive.reason, "Moved away");
+
+        for (stored, expected) in [(2, Some(2)), (0, None)] {
+            let course = Encode!(&ArchivedCourseWithRequiredTeacher {
+                record: CourseWithRequiredTeacher {
+                    id: 3,
+                    name: "Algebra".to_string(),
+                    description: "Linear equations".to_string(),
+                    teacher_id: stored,
+                    student_ids: vec![7],
+                    syllabus: "Chapters 1-4".to_string(),
+                    course_materials: vec!["https://example.com/algebra".to_string()],
+                    metadata: metadata(),
+                },
+                archive: archive(),
+            })
+            .unwrap();
+            let archived: Archived<Course> = decode_archived(&course);
+            assert_course(&archived.record, expected);
+            assert_eq!(archived.archive.archived_at, archive().archived_at);
+        }
+
+        for (stored, expected) in [(3, Some(3)), (0, None)] {
+            let classroom = Encode!(&ArchivedClassroomWithRequiredCourse {
+                record: ClassroomWithRequiredCourse {
+                    id: 4,
+                    name: "Room 101".to_string(),
+                    location: "First floor".to_string(),
+                    capacity: 30,
+                    current_course_id: stored,
+                    equipment: vec!["Projector".to_string()],
+                    metadata: metadata(),
+                },
+                archive: archive(),
+            })
+            .unwrap();
+            let archived: Archived<Classroom> = decode_archived(&classroom);
+            assert_classroom(&archived.record, expected);
+        }
+
+        let course = Encode!(&Archived {
+            record: Course {
+                id: CourseId::from(3),
+                name: "Algebra".to_string(),
+                description: "Linear equations".to_string(),
+                teacher_id: None,
+                student_ids: vec![StudentId::from(7)],
+                syllabus: "Chapters 1-4".to_string(),
+                course_materials: vec!["https://example.com/algebra".to_string()],
+                metadata: metadata(),
+            },
+            archive: archive(),
+        })
+        .unwrap();
+        let archived: Archived<Course> = decode_archived(&course);
+        assert_course(&archived.record, None);
+    }
+
+    #[test]
+    fn round_trips_archives_in_envelope() {
+        let bytes = encode_archived(&Archived {
+            record: guardian(),
+            archive: archive(),
+        });
+        assert!(bytes.starts_with(ENVELOPE_MAGIC));
+        assert!(bytes.len() <= <Archived<Guardian> as BoundedStorable>::MAX_SIZE as usize);
+
+        let archived: Archived<Guardian> = decode_archived(&bytes);
+        assert_eq!(archived.record.name, "Grace");
+        assert_eq!(archived.archive.reason, "Moved away");
+    }
+
+    #[test]
+    fn parses_text_dates() {
+        assert_eq!(Date::parse("2014-02-28"), Some(date(2014, 2, 28)));
+        assert_eq!(Date::parse(" 2012-02-29 "), Some(date(2012, 2, 29)));
+        assert_eq!(Date::parse("2013-02-29"), None);
+        assert_eq!(Date::parse("2014-2"), None);
+        assert_eq!(Date::parse(""), None);
+    }
+}
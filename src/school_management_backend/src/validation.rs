@@ -0,0 +1,379 @@
+//! Input validation shared by the canister endpoints.
+//!
+//! The string limits are sized so that a record with every field at its limit, and its
+//! ID lists at their caps, still encodes within the `BoundedStorable::MAX_SIZE` of its type.
+
+use crate::{Date, Error, FieldError};
+
+/// Maximum length in bytes of names, subject areas and locations.
+pub const MAX_NAME_LENGTH: usize = 128;
+
+/// Maximum length in bytes of an email address.
+pub const MAX_EMAIL_LENGTH: usize = 254;
+
+/// Maximum length in bytes of addresses and qualifications.
+pub const MAX_TEXT_LENGTH: usize = 256;
+
+/// Maximum length in bytes of descriptions and notes.
+pub const MAX_LONG_TEXT_LENGTH: usize = 512;
+
+/// Maximum length in bytes of a phone number.
+pub const MAX_PHONE_LENGTH: usize = 32;
+
+/// Highest grade level a student can be in.
+pub const MAX_GRADE_LEVEL: u8 = 12;
+
+/// Collects field errors so that every problem with an input is reported at once.
+#[derive(Default)]
+pub struct Validator {
+    errors: Vec<FieldError>,
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records an error for the field unless the condition holds.
+    pub fn check(&mut self, field: &str, condition: bool, reason: &str) -> &mut Self {
+        if !condition {
+            self.errors.push(FieldError {
+                field: field.to_string(),
+                reason: reason.to_string(),
+            });
+        }
+        self
+    }
+
+    /// Requires a non-blank value of at most `max_length` bytes.
+    pub fn required(&mut self, field: &str, value: &str, max_length: usize) -> &mut Self {
+        if value.trim().is_empty() {
+            return self.check(field, false, "Field is required");
+        }
+        self.max_length(field, value, max_length)
+    }
+
+    /// Requires a value of at most `max_length` bytes.
+    pub fn max_length(&mut self, field: &str, value: &str, max_length: usize) -> &mut Self {
+        self.check(
+            field,
+            value.len() <= max_length,
+            &format!("Must be at most {} bytes", max_length),
+        )
+    }
+
+    /// Requires a syntactically valid email address.
+    pub fn email(&mut self, field: &str, value: &str) -> &mut Self {
+        if value.trim().is_empty() {
+            return self.check(field, false, "Field is required");
+        }
+        self.max_length(field, value, MAX_EMAIL_LENGTH).check(
+            field,
+            is_valid_email(value),
+            "Must be a valid email address",
+        )
+    }
+
+    /// Requires a phone number of digits, optionally with a leading `+` and separators.
+    pub fn phone(&mut self, field: &str, value: &str) -> &mut Self {
+        self.max_length(field, value, MAX_PHONE_LENGTH).check(
+            field,
+            is_valid_phone(value),
+            "Must be a valid phone number",
+        )
+    }
+
+    /// Requires a value within the inclusive range.
+    pub fn range<T: PartialOrd + std::fmt::Display>(
+        &mut self,
+        field: &str,
+        value: T,
+        min: T,
+        max: T,
+    ) -> &mut Self {
+        let reason = format!("Must be between {} and {}", min, max);
+        self.check(field, value >= min && value <= max, &reason)
+    }
+
+    /// Requires a real calendar date that is not after `today`.
+    pub fn past_date(&mut self, field: &str, date: Date, today: Date) -> &mut Self {
+        if !date.is_valid() {
+            return self.check(field, false, "Must be a valid calendar date");
+        }
+        self.check(field, date <= today, "Must not be in the future")
+    }
+
+    /// Returns every recorded error, or `Ok` if there were none.
+    pub fn finish(&mut self) -> Result<(), Error> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::ValidationFailed {
+                errors: std::mem::take(&mut self.errors),
+            })
+        }
+    }
+}
+
+/// Returns the longest prefix of the value of at most `max_length` bytes that ends on a
+/// character boundary.
+pub fn truncate(value: &str, max_length: usize) -> &str {
+    let mut end = value.len().min(max_length);
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    &value[..end]
+}
+
+/// Returns the first word of free text that passes the email check.
+pub fn find_email(text: &str) -> Option<&str> {
+    text.split(|c: char| c.is_whitespace() || matches!(c, ',' | ';'))
+        .map(|word| {
+            word.trim_matches(|c: char| {
+                matches!(c, '<' | '>' | '(' | ')' | '[' | ']' | '"' | ':' | '.')
+            })
+        })
+        .find(|word| word.len() <= MAX_EMAIL_LENGTH && is_valid_email(word))
+}
+
+/// Returns the first run of free text that passes the phone check, skipping dates.
+pub fn find_phone(text: &str) -> Option<&str> {
+    let is_phone_char =
+        |c: char| c.is_ascii_digit() || matches!(c, '+' | ' ' | '-' | '.' | '(' | ')');
+    text.split(|c: char| !is_phone_char(c))
+        .map(|run| {
+            run.trim_start_matches(|c: char| !(c.is_ascii_digit() || matches!(c, '+' | '(')))
+                .trim_end_matches(|c: char| !c.is_ascii_digit())
+        })
+        .find(|run| {
+            run.len() <= MAX_PHONE_LENGTH && is_valid_phone(run) && Date::parse(run).is_none()
+        })
+}
+
+/// Returns whether the value looks like `local@domain.tld`, without whitespace.
+fn is_valid_email(value: &str) -> bool {
+    let Some((local, domain)) = value.split_once('@') else {
+        return false;
+    };
+    !local.is_empty()
+        && !domain.contains('@')
+        && !value.chars().any(char::is_whitespace)
+        && domain.contains('.')
+        && domain.split('.').all(|label| !label.is_empty())
+}
+
+/// Returns whether the value has at least five digits and otherwise only a leading `+`,
+/// spaces, dashes, dots and parentheses.
+fn is_valid_phone(value: &str) -> bool {
+    let digits = value.chars().filter(char::is_ascii_digit).count();
+    let number = value.strip_prefix('+').unwrap_or(value);
+    digits >= 5
+        && number
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, ' ' | '-' | '.' | '(' | ')'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the reasons recorded against the field by the checks.
+    fn reasons(checks: impl FnOnce(&mut Validator) -> &mut Validator) -> Vec<String> {
+        match checks(&mut Validator::new()).finish() {
+            Ok(()) => vec![],
+            Err(Error::ValidationFailed { errors }) => errors
+                .into_iter()
+                .inspect(|error| assert_eq!(error.field, "field"))
+                .map(|error| error.reason)
+                .collect(),
+            Err(error) => panic!("Unexpected error {:?}", error),
+        }
+    }
+
+    #[test]
+    fn accepts_valid_emails() {
+        for email in [
+            "ada@example.com",
+            "Ada.Lovelace+school@mail.example.co.uk",
+            "a@b.c",
+            "o'brien@example.ie",
+        ] {
+            assert!(reasons(|v| v.email("field", email)).is_empty(), "{}", email);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_emails() {
+        for email in [
+            "ada",
+            "ada@",
+            "@example.com",
+            "ada@example",
+            "ada@example.",
+            "ada@.com",
+            "ada@example..com",
+            "ada@@example.com",
+            "ada@mail@example.com",
+            "ada lovelace@example.com",
+            "ada@example.com\n",
+        ] {
+            assert_eq!(
+                reasons(|v| v.email("field", email)),
+                vec!["Must be a valid email address"],
+                "{:?}",
+                email
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_blank_and_overlong_emails() {
+        assert_eq!(
+            reasons(|v| v.email("field", "  ")),
+            vec!["Field is required"]
+        );
+
+        let overlong = format!("{}@example.com", "a".repeat(MAX_EMAIL_LENGTH));
+        assert_eq!(
+            reasons(|v| v.email("field", &overlong)),
+            vec!["Must be at most 254 bytes"]
+        );
+        let longest = format!("{}@example.com", "a".repeat(MAX_EMAIL_LENGTH - 12));
+        assert!(reasons(|v| v.email("field", &longest)).is_empty());
+    }
+
+    #[test]
+    fn checks_inclusive_ranges() {
+        assert!(reasons(|v| v.range("field", 0, 0, MAX_GRADE_LEVEL)).is_empty());
+        assert!(reasons(|v| v.range("field", 12, 0, MAX_GRADE_LEVEL)).is_empty());
+        assert_eq!(
+            reasons(|v| v.range("field", 13, 0, MAX_GRADE_LEVEL)),
+            vec!["Must be between 0 and 12"]
+        );
+        assert_eq!(
+            reasons(|v| v.range("field", 0.5, 1.0, 100.0)),
+            vec!["Must be between 1 and 100"]
+        );
+        assert_eq!(
+            reasons(|v| v.range("field", f64::NAN, 0.0, 100.0)),
+            vec!["Must be between 0 and 100"]
+        );
+    }
+
+    #[test]
+    fn checks_past_dates() {
+        let date = |year, month, day| Date { year, month, day };
+        let today = date(2025, 9, 1);
+
+        assert!(reasons(|v| v.past_date("field", date(2014, 2, 28), today)).is_empty());
+        assert!(reasons(|v| v.past_date("field", today, today)).is_empty());
+        assert_eq!(
+            reasons(|v| v.past_date("field", date(2025, 9, 2), today)),
+            vec!["Must not be in the future"]
+        );
+        assert_eq!(
+            reasons(|v| v.past_date("field", date(2023, 2, 29), today)),
+            vec!["Must be a valid calendar date"]
+        );
+        assert_eq!(
+            reasons(|v| v.past_date("field", Date::default(), today)),
+            vec!["Must be a valid calendar date"]
+        );
+    }
+
+    #[test]
+    fn reports_every_failed_check() {
+        let mut validator = Validator::new();
+        validator
+            .required("name", "", MAX_NAME_LENGTH)
+            .required("address", "1 Main St", MAX_TEXT_LENGTH)
+            .email("email", "ada")
+            .range("grade_level", 13, 0, MAX_GRADE_LEVEL);
+        let Err(Error::ValidationFailed { errors }) = validator.finish() else {
+            panic!("Validation passed");
+        };
+        let fields: Vec<&str> = errors.iter().map(|error| error.field.as_str()).collect();
+        assert_eq!(fields, vec!["name", "email", "grade_level"]);
+        assert!(validator.finish().is_ok());
+    }
+
+    #[test]
+    fn accepts_valid_phone_numbers() {
+        for phone in [
+            "55501",
+            "+44 20 7946 0958",
+            "(555) 010-0100",
+            "555.010.0100",
+            "+1-555-0100",
+        ] {
+            assert!(reasons(|v| v.phone("field", phone)).is_empty(), "{}", phone);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_phone_numbers() {
+        for phone in [
+            "",
+            "5550",
+            "+ 12",
+            "555-0100 ext 2",
+            "++44 20 7946",
+            "44+20 7946 0958",
+            "555_0100",
+            "０５５５０１００",
+        ] {
+            assert_eq!(
+                reasons(|v| v.phone("field", phone)),
+                vec!["Must be a valid phone number"],
+                "{:?}",
+                phone
+            );
+        }
+        assert_eq!(
+            reasons(|v| v.phone("field", &"1".repeat(MAX_PHONE_LENGTH + 1))),
+            vec!["Must be at most 32 bytes"]
+        );
+    }
+
+    #[test]
+    fn truncates_on_a_char_boundary() {
+        assert_eq!(truncate("Grace", 10), "Grace");
+        assert_eq!(truncate("Grace", 3), "Gra");
+        assert_eq!(truncate("Zoë", 3), "Zo");
+        assert_eq!(truncate("Zoë", 4), "Zoë");
+        assert_eq!(truncate("", 0), "");
+    }
+
+    #[test]
+    fn finds_the_first_email_in_free_text() {
+        assert_eq!(
+            find_email("Grace Hopper, grace@example.com; 555-0100"),
+            Some("grace@example.com")
+        );
+        assert_eq!(
+            find_email("Mother: Grace <grace@example.com>."),
+            Some("grace@example.com")
+        );
+        assert_eq!(
+            find_email("Email grace@example.com or alan@example.com"),
+            Some("grace@example.com")
+        );
+        assert_eq!(find_email("Grace @ home, grace@localhost"), None);
+        assert_eq!(find_email("Grace Hopper"), None);
+    }
+
+    #[test]
+    fn finds_the_first_phone_number_in_free_text() {
+        assert_eq!(find_phone("Grace, 555-0100"), Some("555-0100"));
+        assert_eq!(
+            find_phone("Grace (mother) +44 20 7946 0958, evenings"),
+            Some("+44 20 7946 0958")
+        );
+        assert_eq!(
+            find_phone("Grace, born 1980-01-01, tel. (555) 010-0100"),
+            Some("(555) 010-0100")
+        );
+        assert_eq!(find_phone("Grace, flat 12"), None);
+        assert_eq!(find_phone(&format!("Grace {}", "1".repeat(40))), None);
+    }
+}